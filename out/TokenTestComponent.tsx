@@ -1,28 +1,218 @@
-import React, { useState, useEffect } from 'react';
+import React, { useEffect, useRef, useState } from 'react';
 import {
+  ContractFunctionRevertedError,
   createPublicClient,
+  createTestClient,
+  concat,
   createWalletClient,
+  encodeAbiParameters,
+  encodePacked,
+  formatAbiItem,
+  formatUnits,
   http,
+  keccak256,
   parseAbi,
-  getContract,
+  parseAbiParameters,
+  parseEventLogs,
+  toFunctionSelector,
+  toHex,
 } from "viem";
 import { anvil } from "viem/chains";
 import { privateKeyToAccount } from "viem/accounts";
 
+const contractABI = parseAbi([
+  "constructor(string _name, string _symbol, uint8 _decimals)",
+  "function allowance(address, address) view returns (uint256)",
+  "function approve(address spender, uint256 amount) returns (bool)",
+  "function balanceOf(address) view returns (uint256)",
+  "function mint(address to, uint256 amount)",
+  "function transfer(address to, uint256 amount) returns (bool)",
+  "function transferFrom(address from, address to, uint256 amount) returns (bool)",
+  "event Approval(address indexed owner, address indexed spender, uint256 value)",
+  "event Transfer(address indexed from, address indexed to, uint256 value)",
+]);
+
+// Creation bytecode from the Forge artifact, keyed by contract name.
+const BYTECODE = {
+};
+
+// `Token.transfer.selector` or `Err.selector`, looked up by name in the ABI.
+const selectorOf = (name) => {
+  const item = contractABI.find((item) => (item.type === "function" || item.type === "error") && item.name === name);
+  if (!item) {
+    throw new Error(`selector: ${name} is not in the ABI`);
+  }
+  return toFunctionSelector({ ...item, type: "function" });
+};
+
+// forge's default `msg.sender` and `tx.origin`, also inside the test contract.
+const DEFAULT_SENDER = "0x1804c8AB1F12E6bbf3894d4083f33e07309d1f38";
+
+// forge-std's `makeAddr(name)`: the address of the private key `keccak256(name)`.
+const makeAddr = (name) => privateKeyToAccount(keccak256(toHex(name))).address;
+
+// `vm.addr(privateKey)`.
+const addressOf = (privateKey) => privateKeyToAccount(toHex(privateKey, { size: 32 })).address;
+
+// Thrown when a test fails the way forge fails it: a broken assertion or an
+// unmet vm.expectRevert or vm.expectEmit. Together with contract reverts these
+// are the only errors a `testFail*` test passes on.
+class TestFailure extends Error {}
+
+// Thrown by `assume` when the inputs break a `vm.assume`, which rejects the
+// inputs instead of failing the test, as in a fuzz run.
+class AssumptionRejected extends Error {}
+
+const assume = (condition, source) => {
+  if (!condition) {
+    throw new AssumptionRejected(`vm.assume(${source}) rejected the inputs`);
+  }
+};
+
+// Thrown by `vm.skip(true)`: the test stops and is reported as skipped.
+class TestSkipped extends Error {}
+
+const skipTest = (condition) => {
+  if (condition) {
+    throw new TestSkipped("vm.skip(true)");
+  }
+};
+
+// forge-std's `bound`: values already in range are kept, anything else wraps
+// into [min, max] rather than being clamped to an edge.
+const bound = (x, min, max) => {
+  if (min > max) {
+    throw new Error("bound: max is less than min");
+  }
+  if (x >= min && x <= max) {
+    return x;
+  }
+  const UINT256_MAX = 2n ** 256n - 1n;
+  const size = max - min + 1n;
+  if (x >= 0n && x <= 3n && size > x) {
+    return min + x;
+  }
+  if (x >= UINT256_MAX - 3n && size > UINT256_MAX - x) {
+    return max - (UINT256_MAX - x);
+  }
+  if (x > max) {
+    const rem = (x - max) % size;
+    return rem === 0n ? max : min + rem - 1n;
+  }
+  const rem = (min - x) % size;
+  return rem === 0n ? min : max - rem + 1n;
+};
+
+// Fuzz inputs are edited as text and parsed by their Solidity type on each run.
+const integerRange = (type) => {
+  const bits = BigInt(type.replace(/^u?int/, "") || "256");
+  return type.startsWith("uint") ? [0n, 2n ** bits - 1n] : [-(2n ** (bits - 1n)), 2n ** (bits - 1n) - 1n];
+};
+
+const defaultInput = (type) => {
+  if (/^u?int\d*$/.test(type)) {
+    return "0";
+  }
+  if (type === "bool") {
+    return "false";
+  }
+  if (type === "address") {
+    return `0x${"0".repeat(40)}`;
+  }
+  if (/^bytes\d+$/.test(type)) {
+    return `0x${"00".repeat(Number(type.slice(5)))}`;
+  }
+  if (type === "bytes") {
+    return "0x";
+  }
+  return type === "string" ? "" : "[]";
+};
+
+const parseInput = (type, text) => {
+  if (/^u?int\d*$/.test(type)) {
+    return BigInt(text);
+  }
+  if (type === "bool") {
+    return text === "true";
+  }
+  if (type === "string" || type === "address" || type.startsWith("bytes")) {
+    return text;
+  }
+  // Arrays and structs are entered as JSON.
+  return JSON.parse(text);
+};
+
+// A random value for `type` as input text, within `min`/`max` for integers.
+const randomInput = (type, min, max) => {
+  const randomHex = (size) => toHex(crypto.getRandomValues(new Uint8Array(size)));
+  if (/^u?int\d*$/.test(type)) {
+    const [lowest, highest] = integerRange(type);
+    const low = min ?? lowest;
+    const high = max ?? highest;
+    return String(low + (BigInt(randomHex(40)) % (high - low + 1n)));
+  }
+  if (type === "bool") {
+    return String(Math.random() < 0.5);
+  }
+  if (type === "address") {
+    return randomHex(20);
+  }
+  if (/^bytes\d+$/.test(type)) {
+    return randomHex(Number(type.slice(5)));
+  }
+  if (type === "bytes") {
+    return randomHex(Math.floor(Math.random() * 64));
+  }
+  if (type === "string") {
+    return Math.random().toString(36).slice(2);
+  }
+  return defaultInput(type);
+};
+
 const TokenTestTestComponent = () => {
-  const [publicClient, setPublicClient] = useState(null);
-  const [walletClient, setWalletClient] = useState(null);
-  const [contract, setContract] = useState(null);
-  const [token, setToken] = useState(null);
-  const [alice, setAlice] = useState(null);
-  const [bob, setBob] = useState(null);
+  const clients = useRef({});
+  // Outcome per test name, as { status: "passed" | "failed" | "rejected" |
+  // "skipped", message }. Tests that start with `vm.skip(true)` are skipped
+  // from the start.
+  const [results, setResults] = useState({
+  });
+  // Fuzz inputs per test, as text by parameter name.
+  const [inputs, setInputs] = useState({
+  });
+
+  const setInput = (test, param, value) => {
+    setInputs((inputs) => ({ ...inputs, [test]: { ...inputs[test], [param]: value } }));
+  };
+
+  const randomize = {
+  };
+
+  // The test contract's state variables; contract-typed ones hold addresses.
+  const state = useRef({}).current;
+  // The active vm.prank/vm.startPrank, as { sender, once }.
+  const pranked = useRef(null);
+  // The pending vm.expectRevert, applied to the next contract call.
+  const expectedRevert = useRef(null);
+  // Events from vm.expectEmit, checked against the next transaction's logs.
+  const expectedEvents = useRef([]);
+  // ERC20 balance mapping slots found by `dealToken`, by token address.
+  const balanceSlots = useRef({});
+  // Set when setUp calls `vm.skip(true)`, which skips every test.
+  const setupSkipped = useRef(false);
+  // Settles once setUp has run.
+  const ready = useRef(null);
+  // The chain snapshot and test contract state right after setUp. Every test
+  // starts from it, so tests do not see each other's changes, as in forge.
+  const baseline = useRef(null);
+  // Contracts deployed so far, as { address, contractName }: the invariant
+  // explorer's default targets.
+  const deployed = useRef([]);
+  // The invariant configuration from `targetContract`, `excludeSender`, ...
+  const targets = useRef({ Contract: [], ExcludeContract: [], Selector: [], Sender: [], ExcludeSender: [] });
 
   useEffect(() => {
     const setup = async () => {
       const account = privateKeyToAccount(import.meta.env.PRIVATE_KEY);
-      const tokenAccount = privateKeyToAccount(import.meta.env.TOKEN_PRIVATE_KEY);
-      const aliceAccount = privateKeyToAccount(import.meta.env.ALICE_PRIVATE_KEY);
-      const bobAccount = privateKeyToAccount(import.meta.env.BOB_PRIVATE_KEY);
 
       const publicClient = createPublicClient({
         chain: anvil,
@@ -35,86 +225,497 @@ const TokenTestTestComponent = () => {
         transport: http(import.meta.env.RPC_URL),
       });
 
-      setPublicClient(publicClient);
-      setWalletClient(walletClient);
-      setToken(tokenAccount);
-      setAlice(aliceAccount);
-      setBob(bobAccount);
-
-      const contractABI = parseAbi([
-        "function mint(address, address) public",
-        "function prank(address) public",
-        "function startPrank(address) public",
-        "function stopPrank() public",
-        "function transfer(address, address) public",
-      ]);
-
-      const contract = getContract({
-        address: import.meta.env.CONTRACT_ADDRESS,
-        abi: contractABI,
-        publicClient,
-        walletClient,
+      const testClient = createTestClient({
+        chain: anvil,
+        mode: "anvil",
+        transport: http(import.meta.env.RPC_URL),
       });
 
-      setContract(contract);
+      clients.current = { publicClient, walletClient, testClient };
+
+      state.token = import.meta.env.CONTRACT_ADDRESS;
+      state.alice = "0x0000000000000000000000000000000000000001";
+      state.bob = "0x0000000000000000000000000000000000000002";
 
-      await contract.write.mint([\"alice\, \"1000\]);
+      // new Token("TestToken", "TTK", 18n) needs the artifact bytecode; keeping the default address
+      (await send(state.token, "mint", [state.alice, 1000000000000000000000n]));
+      await saveBaseline();
     };
 
-    setup();
+    ready.current = setup().catch((error) => {
+      if (!(error instanceof TestSkipped)) {
+        throw error;
+      }
+      setupSkipped.current = true;
+    });
   }, []);
 
+  const saveBaseline = async () => {
+    baseline.current = {
+      snapshot: await clients.current.testClient.snapshot(),
+      state: structuredClone(state),
+      deployed: [...deployed.current],
+    };
+  };
 
-const testTransfer = async () => {
-  try {
-    // VM operation: startPrank(\"alice\)
-    console.assert(await \"token\.Identifier { loc: File(0, 465, 473)(\"bob\), 'assertTrue failed');
-    const result = await \"token\.Identifier { loc: File(0, 512, 521)(\"bob\);
-    console.assert(result === \"100\, 'assertEq failed: \"token\.Identifier { loc: File(0, 512, 521)(\"bob\) !== \"100\');
-    const result = await \"token\.Identifier { loc: File(0, 560, 569)(\"alice\);
-    console.assert(result === \"900\, 'assertEq failed: \"token\.Identifier { loc: File(0, 560, 569)(\"alice\) !== \"900\');
-    // VM operation: stopPrank()
-    console.log('testTransfer passed');
-  } catch (error) {
-    console.error('testTransfer failed:', error);
-  }
-};
+  // Anvil drops a snapshot once it is reverted to, so a new one is taken
+  // right away for the next test.
+  const restoreBaseline = async () => {
+    await ready.current;
+    const { testClient } = clients.current;
+    const saved = baseline.current;
+    if (!saved) {
+      return;
+    }
+    await testClient.revert({ id: saved.snapshot });
+    saved.snapshot = await testClient.snapshot();
+    for (const name of Object.keys(state)) {
+      delete state[name];
+    }
+    Object.assign(state, structuredClone(saved.state));
+    deployed.current = [...saved.deployed];
+  };
 
-const testFailTransferInsufficientBalance = async () => {
-  try {
-    // VM operation: prank(\"alice\)
-    await contract.write.transfer([\"bob\, \"2000\]);
-    console.log('testFailTransferInsufficientBalance passed');
-  } catch (error) {
-    console.error('testFailTransferInsufficientBalance failed:', error);
-  }
-};
+  // Pranked calls go out from the impersonated address itself. Like forge,
+  // they pay no gas, so Anvil has to run with `--base-fee 0`.
+  const prank = async (sender) => {
+    await clients.current.testClient.impersonateAccount({ address: sender });
+    pranked.current = { sender, once: true };
+  };
 
-const testApproveAndTransferFrom = async () => {
-  try {
-    // VM operation: prank(\"alice\)
-    console.assert(await \"token\.Identifier { loc: File(0, 849, 856)(\"bob\), 'assertTrue failed');
-    // VM operation: prank(\"bob\)
-    console.assert(await \"token\.Identifier { loc: File(0, 929, 941)(\"alice\), 'assertTrue failed');
-    const result = await \"token\.Identifier { loc: File(0, 986, 995)(\"bob\);
-    console.assert(result === \"50\, 'assertEq failed: \"token\.Identifier { loc: File(0, 986, 995)(\"bob\) !== \"50\');
-    const result = await \"token\.Identifier { loc: File(0, 1033, 1042)(\"alice\);
-    console.assert(result === \"950\, 'assertEq failed: \"token\.Identifier { loc: File(0, 1033, 1042)(\"alice\) !== \"950\');
-    const result = await \"token\.Identifier { loc: File(0, 1083, 1092)(\"alice\);
-    console.assert(result === \"50\, 'assertEq failed: \"token\.Identifier { loc: File(0, 1083, 1092)(\"alice\) !== \"50\');
-    console.log('testApproveAndTransferFrom passed');
-  } catch (error) {
-    console.error('testApproveAndTransferFrom failed:', error);
-  }
-};
+  const startPrank = async (sender) => {
+    await clients.current.testClient.impersonateAccount({ address: sender });
+    pranked.current = { sender, once: false };
+  };
+
+  const stopPrank = async () => {
+    if (pranked.current) {
+      await clients.current.testClient.stopImpersonatingAccount({ address: pranked.current.sender });
+    }
+    pranked.current = null;
+  };
+
+  // The account and fees for the next call. A one-off prank is used up here.
+  const nextSender = () => {
+    const active = pranked.current;
+    if (!active) {
+      return { account: clients.current.walletClient.account };
+    }
+    if (active.once) {
+      pranked.current = null;
+    }
+    return { account: active.sender, maxFeePerGas: 0n, maxPriorityFeePerGas: 0n };
+  };
+
+  const latestBlock = () => clients.current.publicClient.getBlock();
+
+  // Anvil only applies a new timestamp to the next block, so mine one right
+  // away to make it visible as `block.timestamp`. Anvil rejects timestamps
+  // that are not after the latest block, so rewinding past it fails.
+  const warp = async (timestamp) => {
+    const { testClient } = clients.current;
+    await testClient.setNextBlockTimestamp({ timestamp });
+    await testClient.mine({ blocks: 1 });
+  };
+
+  const skip = async (seconds) => {
+    const { testClient } = clients.current;
+    await testClient.increaseTime({ seconds: Number(seconds) });
+    await testClient.mine({ blocks: 1 });
+  };
+
+  // Mines up to `blockNumber`, since Anvil cannot jump block numbers directly.
+  const roll = async (blockNumber) => {
+    const { testClient } = clients.current;
+    const blocks = blockNumber - (await latestBlock()).number;
+    if (blocks > 0n) {
+      await testClient.mine({ blocks: Number(blocks) });
+    }
+  };
+
+  const deal = async (address, value) => {
+    await clients.current.testClient.setBalance({ address, value });
+  };
+
+  // forge-std's ERC20 `deal`: finds the `balanceOf` mapping by writing a marker
+  // into candidate slots until `balanceOf` reports it, then stores `amount`
+  // there. Both the Solidity and the Vyper mapping layouts are tried.
+  const dealToken = async (token, account, amount) => {
+    const { publicClient, testClient } = clients.current;
+    const slotOf = ({ layout, index }) => keccak256(layout === "solidity"
+      ? encodeAbiParameters([{ type: "address" }, { type: "uint256" }], [account, index])
+      : encodeAbiParameters([{ type: "uint256" }, { type: "address" }], [index, account]));
+    const balanceOf = () => publicClient.readContract({
+      address: token,
+      abi: parseAbi(["function balanceOf(address) view returns (uint256)"]),
+      functionName: "balanceOf",
+      args: [account],
+    });
+
+    let found = balanceSlots.current[token];
+    for (let index = 0n; !found && index < 100n; index++) {
+      for (const layout of ["solidity", "vyper"]) {
+        const slot = slotOf({ layout, index });
+        const original = await publicClient.getStorageAt({ address: token, slot });
+        const marker = 0x1337133713371337n;
+        await testClient.setStorageAt({ address: token, index: slot, value: toHex(marker, { size: 32 }) });
+        const matches = (await balanceOf()) === marker;
+        await testClient.setStorageAt({ address: token, index: slot, value: original ?? toHex(0n, { size: 32 }) });
+        if (matches) {
+          found = { layout, index };
+          break;
+        }
+      }
+    }
+    if (!found) {
+      throw new Error(`deal: could not find the balanceOf slot of ${token}`);
+    }
+    balanceSlots.current[token] = found;
+    await testClient.setStorageAt({ address: token, index: slotOf(found), value: toHex(amount, { size: 32 }) });
+  };
+
+  const expectRevert = (expected) => {
+    expectedRevert.current = expected;
+  };
+
+  // JSON for showing values: bigints as strings, and hex (addresses, bytes)
+  // lowercased since viem returns addresses checksummed.
+  const stringify = (value) => JSON.stringify(value, (_, v) => {
+    if (typeof v === "bigint") {
+      return v.toString();
+    }
+    return typeof v === "string" && /^0x[0-9a-fA-F]*$/.test(v) ? v.toLowerCase() : v;
+  });
+
+  // viem decodes uint8 to uint48 as numbers while literals are bigints, so
+  // integers of either kind are tagged alike, and apart from strings, before
+  // values are compared as JSON.
+  const sameValue = (left, right) => {
+    const key = (value) => JSON.stringify(value, (_, v) => {
+      if (typeof v === "bigint" || Number.isInteger(v)) {
+        return { $integer: v.toString() };
+      }
+      return typeof v === "string" && /^0x[0-9a-fA-F]*$/.test(v) ? v.toLowerCase() : v;
+    });
+    return key(left) === key(right);
+  };
+
+  // Integers as bigints, inside arrays and structs too, so call results mix
+  // with the bigint literals in the test's arithmetic and comparisons.
+  const toBigInt = (value) => {
+    if (Number.isInteger(value)) {
+      return BigInt(value);
+    }
+    if (Array.isArray(value)) {
+      return value.map(toBigInt);
+    }
+    if (typeof value === "object" && value !== null && Object.getPrototypeOf(value) === Object.prototype) {
+      return Object.fromEntries(Object.entries(value).map(([key, entry]) => [key, toBigInt(entry)]));
+    }
+    return value;
+  };
+
+  // Runs `call` under the pending vm.expectRevert, if any. The call then has to
+  // revert, and the reason string or custom error, decoded with the ABI's error
+  // definitions, has to match what was expected.
+  const guarded = async (call) => {
+    const expected = expectedRevert.current;
+    if (!expected) {
+      return call();
+    }
+    expectedRevert.current = null;
+    try {
+      await call();
+    } catch (error) {
+      const reverted = error.walk?.((cause) => cause instanceof ContractFunctionRevertedError);
+      if (!reverted) {
+        throw error;
+      }
+      const matches =
+        (expected.reason === undefined || reverted.reason === expected.reason) &&
+        (expected.error === undefined || reverted.data?.errorName === expected.error) &&
+        (expected.args === undefined || sameValue(reverted.data?.args ?? [], expected.args)) &&
+        (expected.data === undefined || reverted.raw?.toLowerCase() === expected.data.toLowerCase());
+      if (!matches) {
+        const actual = reverted.data
+          ? `${reverted.data.errorName}(${stringify(reverted.data.args ?? [])})`
+          : reverted.reason ?? reverted.raw ?? "no data";
+        throw new TestFailure(`expected revert ${stringify(expected)}, got ${actual}`);
+      }
+      return undefined;
+    }
+    throw new TestFailure(`expected revert ${stringify(expected)}, but the call succeeded`);
+  };
+
+  const expectEmit = (expected) => {
+    expectedEvents.current.push(expected);
+  };
+
+  // Whether a decoded log is the expected event. Only the indexed parameters
+  // whose topic flag is set, and the other parameters if the data flag is set,
+  // are compared.
+  const emitMatches = (expected, log) => {
+    const event = contractABI.find((item) => item.type === "event" && item.name === expected.event);
+    if (!event || log.eventName !== expected.event) {
+      return false;
+    }
+    if (expected.emitter && log.address.toLowerCase() !== expected.emitter.toLowerCase()) {
+      return false;
+    }
+    let topic = 0;
+    return event.inputs.every((input, index) => {
+      const checked = input.indexed ? expected.topics[topic++] : expected.data;
+      const actual = Array.isArray(log.args) ? log.args[index] : log.args[input.name];
+      return !checked || sameValue(actual, expected.args[index]);
+    });
+  };
+
+  // Expected events have to show up in the receipt in the order they were declared.
+  const checkEmits = (receipt) => {
+    const expected = expectedEvents.current;
+    if (expected.length === 0) {
+      return;
+    }
+    expectedEvents.current = [];
+    let next = 0;
+    for (const log of parseEventLogs({ abi: contractABI, logs: receipt.logs })) {
+      if (next < expected.length && emitMatches(expected[next], log)) {
+        next++;
+      }
+    }
+    if (next < expected.length) {
+      throw new TestFailure(`expected event ${expected[next].event}(${stringify(expected[next].args)}) was not emitted`);
+    }
+  };
+
+  // An overload is encoded from its exact signature; the errors stay in so
+  // reverts can still be decoded.
+  const abiFor = (signature) => signature
+    ? [...parseAbi([signature]), ...contractABI.filter((item) => item.type === "error")]
+    : contractABI;
+
+  // Simulates first so state-changing calls still hand back their return value,
+  // then sends the transaction and waits for it to be mined. Overloaded
+  // functions pass their exact signature so viem encodes the right one, and
+  // payable ones the ether sent with `{value: ...}`.
+  const send = (address, functionName, args, signature, value) => guarded(async () => {
+    const { publicClient, walletClient } = clients.current;
+    const { result, request } = await publicClient.simulateContract({
+      address,
+      abi: abiFor(signature),
+      functionName,
+      args,
+      value,
+      ...nextSender(),
+    });
+    const hash = await walletClient.writeContract(request);
+    checkEmits(await publicClient.waitForTransactionReceipt({ hash }));
+    return toBigInt(result);
+  });
+
+  // `to.call{value: value}(data)`: resolves to `(success, returnData)` like
+  // Solidity, so a failing call does not end the test by itself.
+  const callAddress = (to, value, data) => guarded(async () => {
+    const { publicClient, walletClient } = clients.current;
+    const sender = nextSender();
+    try {
+      const { data: returned } = await publicClient.call({ to, value, data, account: sender.account });
+      const hash = await walletClient.sendTransaction({ to, value, data, ...sender });
+      checkEmits(await publicClient.waitForTransactionReceipt({ hash }));
+      return [true, returned ?? "0x"];
+    } catch (error) {
+      return [false, error.walk?.((cause) => cause.data)?.data ?? "0x"];
+    }
+  });
+
+  // `payable(to).transfer(value)`, which reverts when the transfer fails.
+  const transferValue = async (to, value) => {
+    const [success] = await callAddress(to, value, "0x");
+    if (!success) {
+      throw new TestFailure(`transferring ${value} wei to ${to} failed`);
+    }
+  };
+
+  const read = (address, functionName, args, signature) => guarded(async () => {
+    const { publicClient } = clients.current;
+    const { account } = nextSender();
+    return toBigInt(await publicClient.readContract({
+      address,
+      abi: abiFor(signature),
+      functionName,
+      args,
+      account,
+    }));
+  });
+
+  // Deploys `contractName` from its artifact bytecode and resolves to the new address.
+  const deploy = (contractName, args) => guarded(async () => {
+    const { publicClient, walletClient } = clients.current;
+    const hash = await walletClient.deployContract({
+      abi: contractABI,
+      bytecode: BYTECODE[contractName],
+      args,
+      ...nextSender(),
+    });
+    const receipt = await publicClient.waitForTransactionReceipt({ hash });
+    if (receipt.status === "reverted") {
+      throw new TestFailure(`deploying ${contractName} reverted`);
+    }
+    checkEmits(receipt);
+    deployed.current.push({ address: receipt.contractAddress, contractName });
+    return receipt.contractAddress;
+  });
+
+  // `targetSelector` entries keep their selectors alongside the address.
+  const targetInvariant = (kind, address, selectors) => {
+    targets.current[kind].push(selectors ? { address, selectors } : address);
+  };
+
+
+  // forge-std assertions. They throw, so a failed assertion ends the test like a
+  // revert does. Values compare by type: integers numerically, whether viem
+  // decoded them as numbers or bigints, addresses and bytes
+  // case-insensitively, strings and arrays by content. `decimals` only
+  // changes how numbers are shown in the failure message.
+  const check = (kind, operands, { message, decimals } = {}) => {
+    const [left, right, tolerance] = operands.map(toBigInt);
+    const show = (value) => (decimals !== undefined && typeof value === "bigint"
+      ? formatUnits(value, Number(decimals))
+      : stringify(value));
+    const equals = sameValue(left, right);
+    const delta = () => (left > right ? left - right : right - left);
+    let passed;
+    let detail;
+    switch (kind) {
+      case "True":
+        [passed, detail] = [left === true, "expected true, got false"];
+        break;
+      case "False":
+        [passed, detail] = [left === false, "expected false, got true"];
+        break;
+      case "Eq":
+        [passed, detail] = [equals, `${show(left)} != ${show(right)}`];
+        break;
+      case "NotEq":
+        [passed, detail] = [!equals, `${show(left)} == ${show(right)}`];
+        break;
+      case "Gt":
+        [passed, detail] = [left > right, `${show(left)} <= ${show(right)}`];
+        break;
+      case "Ge":
+        [passed, detail] = [left >= right, `${show(left)} < ${show(right)}`];
+        break;
+      case "Lt":
+        [passed, detail] = [left < right, `${show(left)} >= ${show(right)}`];
+        break;
+      case "Le":
+        [passed, detail] = [left <= right, `${show(left)} > ${show(right)}`];
+        break;
+      case "ApproxEqAbs":
+        passed = delta() <= tolerance;
+        detail = `${show(left)} !~= ${show(right)} (max delta ${show(tolerance)}, real delta ${show(delta())})`;
+        break;
+      case "ApproxEqRel": {
+        // Relative to `right`, scaled by 1e18 like forge-std, where 1e18 is 100%.
+        const base = right < 0n ? -right : right;
+        const percent = base === 0n ? (equals ? 0n : tolerance + 1n) : (delta() * 10n ** 18n) / base;
+        passed = percent <= tolerance;
+        detail = `${show(left)} !~= ${show(right)} (max delta ${formatUnits(tolerance, 16)}%, real delta ${formatUnits(percent, 16)}%)`;
+        break;
+      }
+      default:
+        [passed, detail] = [false, "fail() called"];
+    }
+    if (!passed) {
+      throw new TestFailure(message ? `${message}: ${detail}` : detail);
+    }
+  };
+
+  // Runs a test body from the state after setUp and records its outcome.
+  // `testFail*` tests are expected to fail, so their result is inverted,
+  // matching `forge test`; errors that are not test failures, such as an RPC
+  // error, still fail them. A `vm.skip(true)` in the test or in setUp marks it
+  // skipped instead.
+  const runTest = async (name, expectFailure, body) => {
+    // Cheatcode state left over from a test that stopped halfway.
+    pranked.current = null;
+    expectedRevert.current = null;
+    expectedEvents.current = [];
+    try {
+      await restoreBaseline();
+    } catch (error) {
+      console.error(`${name} failed in setUp:`, error);
+      setResults((results) => ({ ...results, [name]: { status: "failed", message: `setUp: ${error.message}` } }));
+      return;
+    }
+    let error = setupSkipped.current ? new TestSkipped("vm.skip(true) in setUp") : null;
+    try {
+      if (error === null) {
+        await body();
+      }
+    } catch (caught) {
+      error = caught;
+    }
+    if (error instanceof TestSkipped) {
+      console.warn(`${name} skipped:`, error.message);
+      setResults((results) => ({ ...results, [name]: { status: "skipped", message: error.message } }));
+      return;
+    }
+    if (error instanceof AssumptionRejected) {
+      console.warn(`${name} skipped:`, error.message);
+      setResults((results) => ({ ...results, [name]: { status: "rejected", message: error.message } }));
+      return;
+    }
+    const failed = error instanceof TestFailure || Boolean(error?.walk?.((cause) => cause instanceof ContractFunctionRevertedError));
+    const passed = expectFailure ? failed : error === null;
+    if (passed) {
+      console.log(`${name} passed`);
+    } else {
+      console.error(`${name} failed:`, error ?? "expected the test to fail");
+    }
+    const status = passed ? "passed" : "failed";
+    setResults((results) => ({ ...results, [name]: { status, message: passed ? "" : error?.message ?? "expected the test to fail" } }));
+  };
+
+const testTransfer = () => runTest("testTransfer", false, async () => {
+    await startPrank(state.alice);
+    check("True", [(await send(state.token, "transfer", [state.bob, 100000000000000000000n]))]);
+    check("Eq", [(await read(state.token, "balanceOf", [state.bob])), 100000000000000000000n]);
+    check("Eq", [(await read(state.token, "balanceOf", [state.alice])), 900000000000000000000n]);
+    await stopPrank();
+});
+
+const testFailTransferInsufficientBalance = () => runTest("testFailTransferInsufficientBalance", true, async () => {
+    await prank(state.alice);
+    (await send(state.token, "transfer", [state.bob, 2000000000000000000000n]));
+});
+
+const testApproveAndTransferFrom = () => runTest("testApproveAndTransferFrom", false, async () => {
+    await prank(state.alice);
+    check("True", [(await send(state.token, "approve", [state.bob, 100000000000000000000n]))]);
+    await prank(state.bob);
+    check("True", [(await send(state.token, "transferFrom", [state.alice, state.bob, 50000000000000000000n]))]);
+    check("Eq", [(await read(state.token, "balanceOf", [state.bob])), 50000000000000000000n]);
+    check("Eq", [(await read(state.token, "balanceOf", [state.alice])), 950000000000000000000n]);
+    check("Eq", [(await read(state.token, "allowance", [state.alice, state.bob])), 50000000000000000000n]);
+});
 
 
   return (
     <div>
       <h1>TokenTest Tests</h1>
-  <button onClick={testTransfer}>Run testTransfer</button>
-  <button onClick={testFailTransferInsufficientBalance}>Run testFailTransferInsufficientBalance</button>
-  <button onClick={testApproveAndTransferFrom}>Run testApproveAndTransferFrom</button>
+  <div>
+    <button onClick={testTransfer}>Run testTransfer</button>
+    <span>{results.testTransfer?.status} {results.testTransfer?.message}</span>
+  </div>
+  <div>
+    <button onClick={testFailTransferInsufficientBalance}>Run testFailTransferInsufficientBalance</button>
+    <span>{results.testFailTransferInsufficientBalance?.status} {results.testFailTransferInsufficientBalance?.message}</span>
+  </div>
+  <div>
+    <button onClick={testApproveAndTransferFrom}>Run testApproveAndTransferFrom</button>
+    <span>{results.testApproveAndTransferFrom?.status} {results.testApproveAndTransferFrom?.message}</span>
+  </div>
     </div>
   );
 };
@@ -1,9 +1,30 @@
+use serde::Serialize;
 use serde_json::Value;
 use eyre::{eyre, Result, WrapErr};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug)]
+/// Everything a contract ABI declares, grouped by entry `type`. When read from
+/// a Forge build artifact, the compiler output that comes with it is kept too.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ContractAbi {
+    pub functions: Vec<ContractFunction>,
+    pub events: Vec<ContractEvent>,
+    pub errors: Vec<ContractError>,
+    pub constructor: Option<Constructor>,
+    pub fallback: Option<Fallback>,
+    pub receive: Option<Receive>,
+    /// Contract name from the artifact's compilation target, if known.
+    pub contract_name: Option<String>,
+    pub bytecode: Option<String>,
+    pub deployed_bytecode: Option<String>,
+    /// Canonical signature to 4-byte selector, e.g. `transfer(address,uint256)` => `a9059cbb`.
+    pub method_identifiers: BTreeMap<String, String>,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ContractFunction {
     pub name: String,
     pub inputs: Vec<FunctionParameter>,
@@ -11,31 +32,285 @@ pub struct ContractFunction {
     pub state_mutability: String,
 }
 
-#[derive(Debug)]
+/// A function, error or constructor parameter. `tuple` types carry their
+/// fields in `components`, recursively; `internal_type` keeps the Solidity-level
+/// name (`struct Vault.Position[]`, `contract IERC20`) that the ABI type erases.
+#[derive(Debug, Clone, Serialize)]
 pub struct FunctionParameter {
     pub name: String,
     pub type_: String,
+    pub internal_type: Option<String>,
+    pub components: Vec<FunctionParameter>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContractEvent {
+    pub name: String,
+    pub inputs: Vec<EventParameter>,
+    pub anonymous: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventParameter {
+    pub name: String,
+    pub type_: String,
+    pub internal_type: Option<String>,
+    pub components: Vec<FunctionParameter>,
+    pub indexed: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContractError {
+    pub name: String,
+    pub inputs: Vec<FunctionParameter>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Constructor {
+    pub inputs: Vec<FunctionParameter>,
+    pub state_mutability: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Fallback {
+    pub state_mutability: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Receive {
+    pub state_mutability: String,
+}
+
+impl ContractFunction {
+    /// Human-readable signature in the form viem's `parseAbi` accepts, e.g.
+    /// `function balanceOf(address owner) view returns (uint256)`.
+    pub fn human_readable(&self) -> String {
+        let mut signature = format!("function {}({})", self.name, format_parameters(&self.inputs));
+        if self.state_mutability != "nonpayable" {
+            signature.push(' ');
+            signature.push_str(&self.state_mutability);
+        }
+        if !self.outputs.is_empty() {
+            signature.push_str(&format!(" returns ({})", format_parameters(&self.outputs)));
+        }
+        signature
+    }
+
+    /// `view` and `pure` functions are read through `eth_call`; everything else
+    /// has to be sent as a transaction.
+    pub fn is_read_only(&self) -> bool {
+        matches!(self.state_mutability.as_str(), "view" | "pure")
+    }
+}
+
+impl ContractEvent {
+    /// e.g. `event Transfer(address indexed from, address indexed to, uint256 value)`.
+    /// viem has no syntax for `anonymous`, so that flag only lives on the model.
+    pub fn human_readable(&self) -> String {
+        let inputs = self.inputs.iter()
+            .map(|param| {
+                let mut formatted = expand_tuple_type(&param.type_, &param.components);
+                if param.indexed {
+                    formatted.push_str(" indexed");
+                }
+                if !param.name.is_empty() {
+                    formatted.push(' ');
+                    formatted.push_str(&param.name);
+                }
+                formatted
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("event {}({})", self.name, inputs)
+    }
+}
+
+impl ContractError {
+    pub fn human_readable(&self) -> String {
+        format!("error {}({})", self.name, format_parameters(&self.inputs))
+    }
+}
+
+impl Constructor {
+    pub fn human_readable(&self) -> String {
+        let mut signature = format!("constructor({})", format_parameters(&self.inputs));
+        if self.state_mutability == "payable" {
+            signature.push_str(" payable");
+        }
+        signature
+    }
+}
+
+impl Fallback {
+    pub fn human_readable(&self) -> String {
+        format!("fallback() external{}", if self.state_mutability == "payable" { " payable" } else { "" })
+    }
+}
+
+impl Receive {
+    pub fn human_readable(&self) -> String {
+        format!("receive() external {}", self.state_mutability)
+    }
+}
+
+impl FunctionParameter {
+    /// The parameter type with tuples spelled out inline, e.g.
+    /// `(address owner, uint256 amount)[]`, which `parseAbi` understands.
+    pub fn human_readable_type(&self) -> String {
+        expand_tuple_type(&self.type_, &self.components)
+    }
+
+    /// The struct name from `internalType`, without its contract prefix or array suffix.
+    pub fn struct_name(&self) -> Option<&str> {
+        let internal_type = self.internal_type.as_deref()?.strip_prefix("struct ")?;
+        let name = internal_type.split('[').next()?;
+        Some(name.rsplit('.').next().unwrap_or(name))
+    }
+
+    /// The TypeScript type viem uses for values of this parameter. Structs are
+    /// referred to by name; see [`FunctionParameter::typescript_fields`].
+    pub fn typescript_type(&self) -> String {
+        let (base, dimensions) = split_array_suffix(&self.type_);
+        let element = match base {
+            "tuple" => match self.struct_name() {
+                Some(name) => name.to_string(),
+                None => self.typescript_fields(),
+            },
+            "address" => "`0x${string}`".to_string(),
+            "bool" => "boolean".to_string(),
+            "string" => "string".to_string(),
+            base if base.starts_with("bytes") => "`0x${string}`".to_string(),
+            // The component turns every integer into a bigint, including the
+            // small ones viem decodes as numbers.
+            base if base.starts_with("uint") || base.starts_with("int") => "bigint".to_string(),
+            _ => "unknown".to_string(),
+        };
+        format!("{}{}", element, dimensions)
+    }
+
+    /// The object type for a tuple's components, `{ owner: `0x${string}`; amount: bigint }`.
+    /// Unnamed components become a positional tuple type instead.
+    pub fn typescript_fields(&self) -> String {
+        if self.components.iter().any(|component| component.name.is_empty()) {
+            let fields: Vec<String> = self.components.iter().map(FunctionParameter::typescript_type).collect();
+            format!("readonly [{}]", fields.join(", "))
+        } else {
+            let fields: Vec<String> = self.components.iter()
+                .map(|component| format!("{}: {}", component.name, component.typescript_type()))
+                .collect();
+            format!("{{ {} }}", fields.join("; "))
+        }
+    }
+}
+
+/// Splits `tuple[2][]` into `("tuple", "[2][]")`.
+fn split_array_suffix(type_: &str) -> (&str, &str) {
+    match type_.find('[') {
+        Some(index) => type_.split_at(index),
+        None => (type_, ""),
+    }
+}
+
+fn expand_tuple_type(type_: &str, components: &[FunctionParameter]) -> String {
+    let (base, dimensions) = split_array_suffix(type_);
+    if base == "tuple" {
+        format!("({}){}", format_parameters(components), dimensions)
+    } else {
+        type_.to_string()
+    }
+}
+
+fn format_parameters(params: &[FunctionParameter]) -> String {
+    params.iter()
+        .map(|param| if param.name.is_empty() {
+            param.human_readable_type()
+        } else {
+            format!("{} {}", param.human_readable_type(), param.name)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
-pub fn parse_abi(path: &Path) -> Result<Vec<ContractFunction>> {
+/// Reads either a bare ABI array or a Forge build artifact
+/// (`out/<File>.sol/<Contract>.json`), which wraps the ABI in an object.
+pub fn parse_abi(path: &Path) -> Result<ContractAbi> {
     let content = fs::read_to_string(path)
         .wrap_err("Failed to read ABI file")?;
 
     let abi: Value = serde_json::from_str(&content)
         .wrap_err("Failed to parse ABI JSON")?;
 
+    if abi.get("abi").is_some() {
+        let mut contract_abi = parse_artifact(&abi)
+            .wrap_err_with(|| format!("Failed to read build artifact {}", path.display()))?;
+        if contract_abi.contract_name.is_none() {
+            contract_abi.contract_name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned());
+        }
+        return Ok(contract_abi);
+    }
+
     let abi_array = abi.as_array()
         .ok_or_else(|| eyre!("ABI is not an array"))?;
+    parse_abi_entries(abi_array)
+}
 
-    abi_array.iter()
-        .filter_map(|item| {
-            if item["type"] == "function" {
-                Some(parse_function(item))
-            } else {
-                None
-            }
-        })
-        .collect::<Result<Vec<_>>>()
+fn parse_artifact(artifact: &Value) -> Result<ContractAbi> {
+    let entries = artifact["abi"].as_array()
+        .ok_or_else(|| eyre!("Artifact `abi` is not an array"))?;
+    let mut contract_abi = parse_abi_entries(entries)?;
+
+    // Forge nests the hex under `object`; other toolchains store it directly.
+    let bytecode = |value: &Value| {
+        value.get("object").unwrap_or(value).as_str()
+            .filter(|hex| !hex.is_empty() && *hex != "0x")
+            .map(str::to_string)
+    };
+    contract_abi.bytecode = bytecode(&artifact["bytecode"]);
+    contract_abi.deployed_bytecode = bytecode(&artifact["deployedBytecode"]);
+
+    if let Some(identifiers) = artifact["methodIdentifiers"].as_object() {
+        contract_abi.method_identifiers = identifiers.iter()
+            .filter_map(|(signature, selector)| Some((signature.clone(), selector.as_str()?.to_string())))
+            .collect();
+    }
+
+    // `metadata` is an object in Forge artifacts but a JSON string in solc's raw output.
+    let metadata = match &artifact["metadata"] {
+        Value::String(raw) => serde_json::from_str(raw).ok(),
+        Value::Null => None,
+        other => Some(other.clone()),
+    };
+    contract_abi.contract_name = metadata.as_ref()
+        .and_then(|metadata| metadata["settings"]["compilationTarget"].as_object())
+        .and_then(|targets| targets.values().next())
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    contract_abi.metadata = metadata;
+
+    Ok(contract_abi)
+}
+
+fn parse_abi_entries(abi_array: &[Value]) -> Result<ContractAbi> {
+    let mut contract_abi = ContractAbi::default();
+    for item in abi_array {
+        match item["type"].as_str() {
+            Some("function") => contract_abi.functions.push(parse_function(item)?),
+            Some("event") => contract_abi.events.push(parse_event(item)?),
+            Some("error") => contract_abi.errors.push(parse_error(item)?),
+            Some("constructor") => contract_abi.constructor = Some(Constructor {
+                inputs: parse_parameters(&item["inputs"])?,
+                state_mutability: parse_state_mutability(item)?,
+            }),
+            Some("fallback") => contract_abi.fallback = Some(Fallback {
+                state_mutability: parse_state_mutability(item)?,
+            }),
+            Some("receive") => contract_abi.receive = Some(Receive {
+                state_mutability: parse_state_mutability(item)?,
+            }),
+            _ => {}
+        }
+    }
+    Ok(contract_abi)
 }
 
 fn parse_function(function: &Value) -> Result<ContractFunction> {
@@ -43,19 +318,60 @@ fn parse_function(function: &Value) -> Result<ContractFunction> {
         name: function["name"].as_str().ok_or_else(|| eyre!("Function name not found"))?.to_string(),
         inputs: parse_parameters(&function["inputs"])?,
         outputs: parse_parameters(&function["outputs"])?,
-        state_mutability: function["stateMutability"].as_str().ok_or_else(|| eyre!("State mutability not found"))?.to_string(),
+        state_mutability: parse_state_mutability(function)?,
     })
 }
 
-fn parse_parameters(params: &Value) -> Result<Vec<FunctionParameter>> {
-    params.as_array()
-        .ok_or_else(|| eyre!("Parameters are not an array"))?
+fn parse_event(event: &Value) -> Result<ContractEvent> {
+    let inputs = event["inputs"].as_array()
+        .ok_or_else(|| eyre!("Event inputs are not an array"))?
         .iter()
         .map(|param| {
-            Ok(FunctionParameter {
-                name: param["name"].as_str().ok_or_else(|| eyre!("Parameter name not found"))?.to_string(),
-                type_: param["type"].as_str().ok_or_else(|| eyre!("Parameter type not found"))?.to_string(),
+            let FunctionParameter { name, type_, internal_type, components } = parse_parameter(param)?;
+            Ok(EventParameter {
+                name,
+                type_,
+                internal_type,
+                components,
+                indexed: param["indexed"].as_bool().unwrap_or(false),
             })
         })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(ContractEvent {
+        name: event["name"].as_str().ok_or_else(|| eyre!("Event name not found"))?.to_string(),
+        inputs,
+        anonymous: event["anonymous"].as_bool().unwrap_or(false),
+    })
+}
+
+fn parse_error(error: &Value) -> Result<ContractError> {
+    Ok(ContractError {
+        name: error["name"].as_str().ok_or_else(|| eyre!("Error name not found"))?.to_string(),
+        inputs: parse_parameters(&error["inputs"])?,
+    })
+}
+
+fn parse_state_mutability(item: &Value) -> Result<String> {
+    Ok(item["stateMutability"].as_str().ok_or_else(|| eyre!("State mutability not found"))?.to_string())
+}
+
+fn parse_parameters(params: &Value) -> Result<Vec<FunctionParameter>> {
+    params.as_array()
+        .ok_or_else(|| eyre!("Parameters are not an array"))?
+        .iter()
+        .map(parse_parameter)
         .collect()
-}
\ No newline at end of file
+}
+
+fn parse_parameter(param: &Value) -> Result<FunctionParameter> {
+    Ok(FunctionParameter {
+        name: param["name"].as_str().ok_or_else(|| eyre!("Parameter name not found"))?.to_string(),
+        type_: param["type"].as_str().ok_or_else(|| eyre!("Parameter type not found"))?.to_string(),
+        internal_type: param["internalType"].as_str().map(str::to_string),
+        components: match param.get("components") {
+            Some(components) => parse_parameters(components)?,
+            None => Vec::new(),
+        },
+    })
+}
@@ -1,9 +1,12 @@
-use solang_parser::pt::{SourceUnit, SourceUnitPart, ContractPart, FunctionDefinition, Statement, Expression, VariableDefinition};
+use solang_parser::pt::{SourceUnit, SourceUnitPart, ContractDefinition, ContractPart, FunctionDefinition, Statement, Expression, VariableDefinition, Identifier, Loc, ParameterList};
 use solang_parser::parse;
 use eyre::{eyre, Result, WrapErr};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
-use std::path::Path;
-use serde::Serialize;
+use std::path::{Component, Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::project::Project;
 
 #[derive(Debug, Serialize)]
 pub struct TestContract {
@@ -11,185 +14,2301 @@ pub struct TestContract {
     pub state_variables: Vec<StateVariable>,
     pub setup: Option<TestFunction>,
     pub test_functions: Vec<TestFunction>,
+    /// `invariant*` functions, checked by the invariant explorer after every call.
+    pub invariants: Vec<TestFunction>,
+    /// `afterInvariant()`, run by the invariant explorer at the end of every run.
+    pub after_invariant: Option<TestFunction>,
+    /// Every other function with a body, such as `_mintAndApprove(user, amount)`,
+    /// which the tests call like local functions.
+    pub helpers: Vec<TestFunction>,
+    /// Constants and enum values from the imported sources that the tests use.
+    pub constants: Vec<Constant>,
+    /// How the contracts, structs, enums and value types declared in the
+    /// sources are passed in the ABI, by type name.
+    pub user_types: HashMap<String, String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct StateVariable {
     pub name: String,
     pub type_: String,
-    pub value: Option<String>,
+    pub value: Option<Expr>,
+}
+
+impl TestContract {
+    /// `setUp`, the tests, the invariants, `afterInvariant` and the helpers.
+    pub fn functions(&self) -> impl Iterator<Item = &TestFunction> {
+        self.setup.iter()
+            .chain(&self.test_functions)
+            .chain(&self.invariants)
+            .chain(&self.after_invariant)
+            .chain(&self.helpers)
+    }
+
+    fn functions_mut(&mut self) -> impl Iterator<Item = &mut TestFunction> {
+        self.setup.iter_mut()
+            .chain(&mut self.test_functions)
+            .chain(&mut self.invariants)
+            .chain(&mut self.after_invariant)
+            .chain(&mut self.helpers)
+    }
+
+    /// The `setUp`, test, invariant or helper called `name`.
+    fn function(&self, name: &str) -> Option<&TestFunction> {
+        self.functions().find(|function| function.name == name)
+    }
 }
 
+/// A constant the tests can use from elsewhere in the sources: `MAX` declared
+/// at file level, `Constants.MAX` declared in a library or another contract,
+/// or `Status.Active`, an enum value numbered from 0.
 #[derive(Debug, Serialize)]
+pub struct Constant {
+    pub scope: Option<String>,
+    pub name: String,
+    pub value: Expr,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct TestFunction {
     pub name: String,
+    /// Fuzz inputs, e.g. `amount` and `to` in `testFuzz_Transfer(uint256 amount, address to)`.
+    pub parameters: Vec<TestParameter>,
     pub steps: Vec<TestStep>,
+    /// `testFail*` tests pass when they revert or an assertion fails, as in `forge test`.
+    pub expect_failure: bool,
+    /// The `vm.assume` conditions anywhere in the body, as written.
+    pub assumptions: Vec<String>,
+    /// Set on helpers that can call themselves, directly or through other helpers.
+    pub recursive: bool,
+    /// The body starts out unconditionally with `vm.skip(true)`, so forge
+    /// reports the test as skipped without running the rest.
+    pub skipped: bool,
 }
 
-#[derive(Debug, Serialize)]
+/// A fuzz input. `min`/`max` come from a `bound(param, min, max)` in the body
+/// when both limits are constants, so inputs can be generated within them.
+#[derive(Debug, Clone, Serialize)]
+pub struct TestParameter {
+    pub name: String,
+    pub type_: String,
+    pub min: Option<Expr>,
+    pub max: Option<Expr>,
+}
+
+impl TestFunction {
+    /// The limits of the first `bound(name, min, max)` in the body, if both are constants.
+    fn constant_bounds(&self, name: &str) -> Option<(Expr, Expr)> {
+        let mut bounds = None;
+        for step in self.all_steps() {
+            for expr in step.expressions() {
+                expr.visit(&mut |expr| {
+                    let Expr::Call { callee, arguments, .. } = expr else { return };
+                    match (callee.as_ref(), arguments.as_slice()) {
+                        (Expr::Variable { name: callee }, [Expr::Variable { name: param }, min, max])
+                            if callee == "bound" && param == name && min.is_constant() && max.is_constant() =>
+                        {
+                            bounds.get_or_insert_with(|| (min.clone(), max.clone()));
+                        }
+                        _ => {}
+                    }
+                });
+            }
+        }
+        bounds
+    }
+
+    /// Names of the local functions called anywhere in the body, as
+    /// `name(args)` statements or inside expressions.
+    pub fn called_functions(&self) -> HashSet<String> {
+        let mut called = HashSet::new();
+        for step in self.all_steps() {
+            if let TestStep::FunctionCall { contract: None, function, .. } = step {
+                called.insert(function.clone());
+            }
+            for expr in step.expressions() {
+                expr.visit(&mut |expr| {
+                    if let Expr::Call { callee, .. } = expr {
+                        if let Expr::Variable { name } = callee.as_ref() {
+                            called.insert(name.clone());
+                        }
+                    }
+                });
+            }
+        }
+        called
+    }
+
+    /// Every step in the function, including those nested in blocks, branches
+    /// and loops, parents first.
+    pub fn all_steps(&self) -> Vec<&TestStep> {
+        let mut all = Vec::new();
+        self.steps.iter().for_each(|step| step.visit(&mut |step| all.push(step)));
+        all
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum TestStep {
+    /// A local variable. `mutable` is set when the function assigns to it
+    /// again later, so the generator can choose between `let` and `const`.
     VariableDeclaration {
         name: String,
         type_: String,
-        value: Option<String>,
+        value: Option<Expr>,
+        mutable: bool,
+        loc: SourceLocation,
+    },
+    /// `(uint a, , bool c) = value`: locals declared from the elements of a
+    /// tuple by position, with `None` for skipped elements. `mutable` is set
+    /// when any of them is assigned again.
+    TupleDeclaration {
+        variables: Vec<Option<TupleVariable>>,
+        value: Expr,
+        mutable: bool,
+        loc: SourceLocation,
+    },
+    /// `(a, , b) = value`: existing variables assigned from the elements of a
+    /// tuple by position, with `None` for skipped elements.
+    TupleAssignment {
+        targets: Vec<Option<Expr>>,
+        value: Expr,
+        loc: SourceLocation,
     },
+    /// `target op value` with `op` one of `=`, `+=`, `-=`, ... Increments and
+    /// decrements are lowered to `+= 1` and `-= 1`.
+    Assignment {
+        target: Expr,
+        op: String,
+        value: Expr,
+        loc: SourceLocation,
+    },
+    /// `delete target`, which resets it to the zero value of `type_` when the
+    /// type is known from a local declaration.
+    Delete {
+        target: Expr,
+        type_: Option<String>,
+        loc: SourceLocation,
+    },
+    /// `new Contract(args)`, optionally assigned to `variable` (`token = new Token(...)`).
     Constructor {
         contract: String,
-        arguments: Vec<String>,
+        arguments: Vec<Expr>,
+        variable: Option<String>,
+        loc: SourceLocation,
     },
     FunctionCall {
         contract: Option<String>,
         function: String,
-        arguments: Vec<String>,
+        arguments: Vec<Expr>,
+        loc: SourceLocation,
     },
-    VMPrank(String),
-    VMStartPrank(String),
+    /// A call statement the other steps do not cover, kept as the call
+    /// expression: `IERC20(address(token)).transfer(to, 1)` on an address cast
+    /// to a contract or interface type, `payable(to).transfer(1)` or
+    /// `token.deposit{value: 1 ether}()`.
+    ContractCall { call: Expr, loc: SourceLocation },
+    /// `vm.prank(sender)`: only the next call is sent from `sender`.
+    VMPrank { sender: Expr, loc: SourceLocation },
+    /// `vm.startPrank(sender)`: calls are sent from `sender` until `vm.stopPrank()`.
+    VMStartPrank { sender: Expr, loc: SourceLocation },
     VMStopPrank,
+    /// `vm.warp(timestamp)`: sets `block.timestamp`.
+    VMWarp { timestamp: Expr, loc: SourceLocation },
+    /// `vm.roll(number)`: sets `block.number`.
+    VMRoll { block_number: Expr, loc: SourceLocation },
+    /// forge-std `skip(seconds)`: moves `block.timestamp` forward.
+    Skip { seconds: Expr, loc: SourceLocation },
+    /// `vm.skip(condition)`: stops the test and reports it as skipped.
+    VMSkip { condition: Expr, loc: SourceLocation },
+    /// forge-std `rewind(seconds)`: moves `block.timestamp` back.
+    Rewind { seconds: Expr, loc: SourceLocation },
+    /// `vm.deal(account, amount)`: sets the native balance.
+    VMDeal { account: Expr, amount: Expr, loc: SourceLocation },
+    /// forge-std `deal(token, account, amount)`: sets an ERC20 balance.
+    DealToken { token: Expr, account: Expr, amount: Expr, loc: SourceLocation },
+    /// forge-std `hoax`/`startHoax`: funds `sender`, then pranks it for one
+    /// call, or until `vm.stopPrank()` when `start` is set.
+    Hoax { sender: Expr, amount: Expr, start: bool, loc: SourceLocation },
+    /// A forge-std assertion. `assert_type` is the name as written, e.g.
+    /// `assertApproxEqAbsDecimal`; `arguments` are the operands, followed by
+    /// the tolerance for the approximate comparisons.
     Assertion {
         assert_type: String,
-        arguments: Vec<String>,
+        kind: AssertionKind,
+        arguments: Vec<Expr>,
+        decimals: Option<Expr>,
+        message: Option<Expr>,
+        loc: SourceLocation,
+    },
+    /// `vm.assume(condition)`: the inputs are rejected unless `condition` holds.
+    /// `source` is the condition as written, for showing it next to the inputs.
+    Assume {
+        condition: Expr,
+        source: String,
+        loc: SourceLocation,
+    },
+    /// `vm.expectRevert(...)`: the next contract call must revert as described.
+    ExpectRevert {
+        expected: RevertExpectation,
+        loc: SourceLocation,
+    },
+    /// `vm.expectEmit(topic1, topic2, topic3, data[, emitter])` followed by
+    /// `emit Event(args)`: the next transaction must log a matching event.
+    /// `check_topics` covers the indexed parameters in order.
+    ExpectEmit {
+        event: String,
+        arguments: Vec<Expr>,
+        check_topics: Vec<bool>,
+        check_data: bool,
+        emitter: Option<Expr>,
+        loc: SourceLocation,
+    },
+    /// `targetContract(addr)`, `excludeSender(addr)`, ... in `setUp`. For
+    /// `targetSelector(FuzzSelector({addr: ..., selectors: ...}))`, `address`
+    /// is the `addr` field and `selectors` the `bytes4[]` of selectors.
+    InvariantTarget {
+        kind: TargetKind,
+        address: Expr,
+        selectors: Option<Expr>,
+        loc: SourceLocation,
+    },
+    /// A nested `{ ... }` or `unchecked { ... }` block.
+    Block {
+        steps: Vec<TestStep>,
+        unchecked: bool,
+    },
+    If {
+        condition: Expr,
+        then_steps: Vec<TestStep>,
+        else_steps: Vec<TestStep>,
+        loc: SourceLocation,
+    },
+    For {
+        init: Option<Box<TestStep>>,
+        condition: Option<Expr>,
+        update: Option<Box<TestStep>>,
+        body: Vec<TestStep>,
+        loc: SourceLocation,
+    },
+    While {
+        condition: Expr,
+        body: Vec<TestStep>,
+        loc: SourceLocation,
+    },
+    DoWhile {
+        body: Vec<TestStep>,
+        condition: Expr,
+        loc: SourceLocation,
+    },
+    Break,
+    Continue,
+    /// `return` or `return value`. In helpers with named return variables a
+    /// bare `return` returns them, as does the end of the body.
+    Return {
+        value: Option<Expr>,
+        loc: SourceLocation,
     },
 }
 
-pub fn parse_foundry_test_file(path: &Path) -> Result<TestContract> {
-    let content = fs::read_to_string(path)
-        .wrap_err("Failed to read Solidity test file")?;
+/// The comparison a forge-std assertion makes, independent of its operand type.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AssertionKind {
+    True,
+    False,
+    Eq,
+    NotEq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    ApproxEqAbs,
+    ApproxEqRel,
+    /// `fail()`, which always fails.
+    Fail,
+}
 
-    let (source_unit, _) = parse(&content, 0)
-        .map_err(|e| eyre!("Failed to parse Solidity content: {:?}", e))?;
+impl AssertionKind {
+    /// The kind of `assertEq`, `assertEqDecimal`, `assertEq32`, ... and whether
+    /// it is a `Decimal` variant taking a `decimals` argument.
+    fn from_name(name: &str) -> Option<(Self, bool)> {
+        if name == "fail" {
+            return Some((AssertionKind::Fail, false));
+        }
+        let name = name.strip_prefix("assert")?;
+        let (name, decimal) = match name.strip_suffix("Decimal") {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        let name = name.strip_suffix("32").or_else(|| name.strip_suffix("Uint")).unwrap_or(name);
+        let kind = match name {
+            "True" => AssertionKind::True,
+            "False" => AssertionKind::False,
+            "Eq" => AssertionKind::Eq,
+            "NotEq" => AssertionKind::NotEq,
+            "Gt" => AssertionKind::Gt,
+            "Ge" => AssertionKind::Ge,
+            "Lt" => AssertionKind::Lt,
+            "Le" => AssertionKind::Le,
+            "ApproxEqAbs" => AssertionKind::ApproxEqAbs,
+            "ApproxEqRel" => AssertionKind::ApproxEqRel,
+            _ => return None,
+        };
+        Some((kind, decimal))
+    }
 
-    extract_test_contract(source_unit)
+    /// Operands taken before the optional `decimals` and message arguments.
+    fn operands(self) -> usize {
+        match self {
+            AssertionKind::Fail => 0,
+            AssertionKind::True | AssertionKind::False => 1,
+            AssertionKind::ApproxEqAbs | AssertionKind::ApproxEqRel => 3,
+            _ => 2,
+        }
+    }
 }
 
+/// Which list of the invariant configuration a `target*`/`exclude*` call adds to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TargetKind {
+    Contract,
+    ExcludeContract,
+    Selector,
+    Sender,
+    ExcludeSender,
+}
 
+impl TargetKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "targetContract" => Some(TargetKind::Contract),
+            "excludeContract" => Some(TargetKind::ExcludeContract),
+            "targetSelector" => Some(TargetKind::Selector),
+            "targetSender" => Some(TargetKind::Sender),
+            "excludeSender" => Some(TargetKind::ExcludeSender),
+            _ => None,
+        }
+    }
+}
 
-fn extract_test_contract(source_unit: SourceUnit) -> Result<TestContract> {
-    for part in source_unit.0 {
-        if let SourceUnitPart::ContractDefinition(contract) = part {
-            if contract.name.as_ref().map_or(false, |name| name.name.ends_with("Test")) {
-                let mut state_variables = Vec::new();
-                let mut setup = None;
-                let mut test_functions = Vec::new();
+/// What a `vm.expectRevert` accepts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum RevertExpectation {
+    /// `vm.expectRevert()`: any revert.
+    Any,
+    /// `vm.expectRevert("reason")` or `vm.expectRevert(bytes("reason"))`.
+    Reason { reason: String },
+    /// `vm.expectRevert(Err.selector)` matches the error by name only;
+    /// `abi.encodeWithSelector(Err.selector, args)` also matches its arguments.
+    CustomError { name: String, arguments: Option<Vec<Expr>> },
+    /// `vm.expectRevert(stdError.arithmeticError)`: a `Panic(uint256)` with
+    /// the code forge-std's `stdError` value encodes.
+    Panic { code: u8 },
+    /// Anything else, compared against the raw revert data.
+    Data { data: Expr },
+}
 
-                for part in &contract.parts {
-                    match part {
-                        ContractPart::VariableDefinition(var) => {
-                            state_variables.push(extract_state_variable(var));
-                        }
-                        ContractPart::FunctionDefinition(func) => {
-                            if func.name.as_ref().map_or(false, |name| name.name == "setUp") {
-                                setup = Some(extract_function(func)?);
-                            } else if is_test_function(func) {
-                                test_functions.push(extract_function(func)?);
-                            }
+/// forge-std's `stdError` values and the `Panic(uint256)` codes they encode.
+const STD_ERRORS: [(&str, u8); 9] = [
+    ("assertionError", 0x01),
+    ("arithmeticError", 0x11),
+    ("divisionError", 0x12),
+    ("enumConversionError", 0x21),
+    ("encodeStorageError", 0x22),
+    ("popError", 0x31),
+    ("indexOOBError", 0x32),
+    ("memOverflowError", 0x41),
+    ("zeroVarError", 0x51),
+];
+
+impl RevertExpectation {
+    fn from_arguments(arguments: &[Expr]) -> Self {
+        let Some(data) = arguments.first() else { return RevertExpectation::Any };
+        match data {
+            Expr::String { value } => RevertExpectation::Reason { reason: value.clone() },
+            Expr::Cast { ty, value } if ty == "bytes" => match value.as_ref() {
+                Expr::String { value } => RevertExpectation::Reason { reason: value.clone() },
+                _ => RevertExpectation::Data { data: data.clone() },
+            },
+            Expr::MemberAccess { object, member } if matches!(object.as_ref(), Expr::Variable { name } if name == "stdError") => {
+                match STD_ERRORS.iter().find(|(name, _)| name == member) {
+                    Some(&(_, code)) => RevertExpectation::Panic { code },
+                    None => RevertExpectation::Data { data: data.clone() },
+                }
+            }
+            Expr::MemberAccess { object, member } if member == "selector" => match error_name(object) {
+                Some(name) => RevertExpectation::CustomError { name, arguments: None },
+                None => RevertExpectation::Data { data: data.clone() },
+            },
+            Expr::Call { callee, arguments, .. } => match (callee.as_ref(), arguments.split_first()) {
+                (
+                    Expr::MemberAccess { object, member },
+                    Some((Expr::MemberAccess { object: error, member: selector }, arguments)),
+                ) if matches!(object.as_ref(), Expr::Variable { name } if name == "abi")
+                    && member == "encodeWithSelector"
+                    && selector == "selector" =>
+                {
+                    match error_name(error) {
+                        Some(name) => RevertExpectation::CustomError { name, arguments: Some(arguments.to_vec()) },
+                        None => RevertExpectation::Data { data: data.clone() },
+                    }
+                }
+                _ => RevertExpectation::Data { data: data.clone() },
+            },
+            _ => RevertExpectation::Data { data: data.clone() },
+        }
+    }
+
+    fn expressions(&self) -> Vec<&Expr> {
+        match self {
+            RevertExpectation::CustomError { arguments: Some(arguments), .. } => arguments.iter().collect(),
+            RevertExpectation::Data { data } => vec![data],
+            _ => vec![],
+        }
+    }
+
+    fn expressions_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            RevertExpectation::CustomError { arguments: Some(arguments), .. } => arguments.iter_mut().collect(),
+            RevertExpectation::Data { data } => vec![data],
+            _ => vec![],
+        }
+    }
+}
+
+/// `Err` or `Token.Err` to the error (or event) name `Err`.
+fn error_name(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Variable { name } => Some(name.clone()),
+        Expr::MemberAccess { member, .. } => Some(member.clone()),
+        _ => None,
+    }
+}
+
+impl TestStep {
+    /// The expressions a step evaluates, in source order.
+    pub fn expressions(&self) -> Vec<&Expr> {
+        match self {
+            TestStep::VariableDeclaration { value, .. } => value.iter().collect(),
+            TestStep::TupleDeclaration { value, .. } => vec![value],
+            TestStep::ContractCall { call, .. } => vec![call],
+            TestStep::TupleAssignment { targets, value, .. } => targets.iter().flatten().chain(std::iter::once(value)).collect(),
+            TestStep::Assignment { target, value, .. } => vec![target, value],
+            TestStep::Delete { target, .. } => vec![target],
+            TestStep::Constructor { arguments, .. }
+            | TestStep::FunctionCall { arguments, .. } => arguments.iter().collect(),
+            TestStep::Assertion { arguments, decimals, message, .. } => arguments.iter().chain(decimals).chain(message).collect(),
+            TestStep::VMPrank { sender, .. } | TestStep::VMStartPrank { sender, .. } => vec![sender],
+            TestStep::VMWarp { timestamp: value, .. }
+            | TestStep::VMRoll { block_number: value, .. }
+            | TestStep::Skip { seconds: value, .. }
+            | TestStep::Rewind { seconds: value, .. } => vec![value],
+            TestStep::VMDeal { account, amount, .. } => vec![account, amount],
+            TestStep::DealToken { token, account, amount, .. } => vec![token, account, amount],
+            TestStep::Hoax { sender, amount, .. } => vec![sender, amount],
+            TestStep::ExpectRevert { expected, .. } => expected.expressions(),
+            TestStep::Assume { condition, .. }
+            | TestStep::VMSkip { condition, .. } => vec![condition],
+            TestStep::InvariantTarget { address, selectors, .. } => std::iter::once(address).chain(selectors).collect(),
+            TestStep::ExpectEmit { arguments, emitter, .. } => arguments.iter().chain(emitter).collect(),
+            TestStep::If { condition, .. } | TestStep::While { condition, .. } | TestStep::DoWhile { condition, .. } => vec![condition],
+            TestStep::For { condition, .. } => condition.iter().collect(),
+            TestStep::Return { value, .. } => value.iter().collect(),
+            TestStep::VMStopPrank | TestStep::Block { .. } | TestStep::Break | TestStep::Continue => vec![],
+        }
+    }
+
+    fn expressions_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            TestStep::VariableDeclaration { value, .. } => value.iter_mut().collect(),
+            TestStep::TupleDeclaration { value, .. } => vec![value],
+            TestStep::ContractCall { call, .. } => vec![call],
+            TestStep::TupleAssignment { targets, value, .. } => targets.iter_mut().flatten().chain(std::iter::once(value)).collect(),
+            TestStep::Assignment { target, value, .. } => vec![target, value],
+            TestStep::Delete { target, .. } => vec![target],
+            TestStep::Constructor { arguments, .. }
+            | TestStep::FunctionCall { arguments, .. } => arguments.iter_mut().collect(),
+            TestStep::Assertion { arguments, decimals, message, .. } => arguments.iter_mut().chain(decimals).chain(message).collect(),
+            TestStep::VMPrank { sender, .. } | TestStep::VMStartPrank { sender, .. } => vec![sender],
+            TestStep::VMWarp { timestamp: value, .. }
+            | TestStep::VMRoll { block_number: value, .. }
+            | TestStep::Skip { seconds: value, .. }
+            | TestStep::Rewind { seconds: value, .. } => vec![value],
+            TestStep::VMDeal { account, amount, .. } => vec![account, amount],
+            TestStep::DealToken { token, account, amount, .. } => vec![token, account, amount],
+            TestStep::Hoax { sender, amount, .. } => vec![sender, amount],
+            TestStep::ExpectRevert { expected, .. } => expected.expressions_mut(),
+            TestStep::Assume { condition, .. }
+            | TestStep::VMSkip { condition, .. } => vec![condition],
+            TestStep::InvariantTarget { address, selectors, .. } => std::iter::once(address).chain(selectors).collect(),
+            TestStep::ExpectEmit { arguments, emitter, .. } => arguments.iter_mut().chain(emitter).collect(),
+            TestStep::If { condition, .. } | TestStep::While { condition, .. } | TestStep::DoWhile { condition, .. } => vec![condition],
+            TestStep::For { condition, .. } => condition.iter_mut().collect(),
+            TestStep::Return { value, .. } => value.iter_mut().collect(),
+            TestStep::VMStopPrank | TestStep::Block { .. } | TestStep::Break | TestStep::Continue => vec![],
+        }
+    }
+
+    /// The steps nested directly inside this one, in source order.
+    fn children(&self) -> Vec<&TestStep> {
+        match self {
+            TestStep::Block { steps, .. } => steps.iter().collect(),
+            TestStep::If { then_steps, else_steps, .. } => then_steps.iter().chain(else_steps).collect(),
+            TestStep::For { init, update, body, .. } => init.as_deref().into_iter().chain(body).chain(update.as_deref()).collect(),
+            TestStep::While { body, .. } | TestStep::DoWhile { body, .. } => body.iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut TestStep> {
+        match self {
+            TestStep::Block { steps, .. } => steps.iter_mut().collect(),
+            TestStep::If { then_steps, else_steps, .. } => then_steps.iter_mut().chain(else_steps).collect(),
+            TestStep::For { init, update, body, .. } => {
+                init.as_deref_mut().into_iter().chain(body).chain(update.as_deref_mut()).collect()
+            }
+            TestStep::While { body, .. } | TestStep::DoWhile { body, .. } => body.iter_mut().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The locals this step declares, as `(name, type)`.
+    pub fn declared(&self) -> Vec<(&String, &String)> {
+        match self {
+            TestStep::VariableDeclaration { name, type_, .. } => vec![(name, type_)],
+            TestStep::TupleDeclaration { variables, .. } => variables.iter().flatten().map(|variable| (&variable.name, &variable.type_)).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Calls `f` on this step and every nested step, parents first.
+    pub fn visit<'a>(&'a self, f: &mut dyn FnMut(&'a TestStep)) {
+        f(self);
+        self.children().into_iter().for_each(|child| child.visit(f));
+    }
+
+    fn visit_mut(&mut self, f: &mut dyn FnMut(&mut TestStep)) {
+        f(self);
+        self.children_mut().into_iter().for_each(|child| child.visit_mut(f));
+    }
+
+    pub fn loc(&self) -> Option<&SourceLocation> {
+        match self {
+            TestStep::VariableDeclaration { loc, .. }
+            | TestStep::TupleDeclaration { loc, .. }
+            | TestStep::TupleAssignment { loc, .. }
+            | TestStep::Assignment { loc, .. }
+            | TestStep::Delete { loc, .. }
+            | TestStep::Constructor { loc, .. }
+            | TestStep::FunctionCall { loc, .. }
+            | TestStep::ContractCall { loc, .. }
+            | TestStep::Assertion { loc, .. }
+            | TestStep::ExpectRevert { loc, .. }
+            | TestStep::Assume { loc, .. }
+            | TestStep::ExpectEmit { loc, .. }
+            | TestStep::If { loc, .. }
+            | TestStep::For { loc, .. }
+            | TestStep::While { loc, .. }
+            | TestStep::DoWhile { loc, .. }
+            | TestStep::Return { loc, .. }
+            | TestStep::VMPrank { loc, .. }
+            | TestStep::VMStartPrank { loc, .. }
+            | TestStep::VMWarp { loc, .. }
+            | TestStep::VMRoll { loc, .. }
+            | TestStep::Skip { loc, .. }
+            | TestStep::VMSkip { loc, .. }
+            | TestStep::Rewind { loc, .. }
+            | TestStep::VMDeal { loc, .. }
+            | TestStep::DealToken { loc, .. }
+            | TestStep::Hoax { loc, .. }
+            | TestStep::InvariantTarget { loc, .. } => Some(loc),
+            TestStep::VMStopPrank
+            | TestStep::Block { .. }
+            | TestStep::Break
+            | TestStep::Continue => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TupleVariable {
+    pub name: String,
+    pub type_: String,
+}
+
+/// Where a step came from in the Solidity source, 1-based like compiler output.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// The file being extracted, used to turn solang byte offsets into lines and columns.
+struct SourceFile {
+    path: String,
+    content: String,
+}
+
+impl SourceFile {
+    fn location(&self, loc: &Loc) -> SourceLocation {
+        let offset = match loc {
+            Loc::File(_, start, _) => (*start).min(self.content.len()),
+            _ => 0,
+        };
+        let before = &self.content[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().map_or(0, |tail| tail.chars().count()) + 1;
+        SourceLocation { file: self.path.clone(), line, column }
+    }
+}
+
+/// A Solidity expression, lowered from the solang parse tree into a shape the
+/// generator can walk without caring about source locations.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Expr {
+    /// Integer literal, normalized to a decimal string with the exponent and
+    /// any unit (`ether`, `days`, ...) applied. Hex literals keep their `0x` form.
+    Number { value: String },
+    Bool { value: bool },
+    String { value: String },
+    /// `hex"..."` literal as a `0x`-prefixed byte string.
+    Hex { value: String },
+    Address { value: String },
+    Variable { name: String },
+    /// An elementary type used as a value, e.g. the argument of `type(uint256)`.
+    Type { name: String },
+    MemberAccess { object: Box<Expr>, member: String },
+    Index { object: Box<Expr>, index: Option<Box<Expr>> },
+    /// A call, with the ether it sends for `f{value: 1 ether}(args)`.
+    Call {
+        callee: Box<Expr>,
+        arguments: Vec<Expr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<Box<Expr>>,
+    },
+    /// Elementary type conversion such as `address(0x1)` or `uint256(x)`.
+    Cast { ty: String, value: Box<Expr> },
+    New { contract: String, arguments: Vec<Expr> },
+    Unary { op: String, operand: Box<Expr> },
+    Binary { op: String, left: Box<Expr>, right: Box<Expr> },
+    Ternary { condition: Box<Expr>, if_true: Box<Expr>, if_false: Box<Expr> },
+    Array { elements: Vec<Expr> },
+    Tuple { elements: Vec<Expr> },
+    /// Struct constructor such as `Vault.Position({owner: a, amount: 5})`, with
+    /// the field names, or `Vault.Position(a, 5)`, with `fields` empty.
+    Struct { name: String, fields: Vec<String>, values: Vec<Expr> },
+    /// Anything we do not translate yet, kept as Solidity source for comments.
+    Unsupported { source: String },
+}
+
+impl Expr {
+    /// Calls `f` on this expression and every sub-expression, parents first.
+    pub fn visit(&self, f: &mut dyn FnMut(&Expr)) {
+        f(self);
+        match self {
+            Expr::MemberAccess { object, .. } => object.visit(f),
+            Expr::Index { object, index } => {
+                object.visit(f);
+                if let Some(index) = index {
+                    index.visit(f);
+                }
+            }
+            Expr::Call { callee, arguments, value } => {
+                callee.visit(f);
+                arguments.iter().for_each(|arg| arg.visit(f));
+                if let Some(value) = value {
+                    value.visit(f);
+                }
+            }
+            Expr::Cast { value, .. } | Expr::Unary { operand: value, .. } => value.visit(f),
+            Expr::Binary { left, right, .. } => {
+                left.visit(f);
+                right.visit(f);
+            }
+            Expr::Ternary { condition, if_true, if_false } => {
+                condition.visit(f);
+                if_true.visit(f);
+                if_false.visit(f);
+            }
+            Expr::New { arguments: elements, .. }
+            | Expr::Array { elements }
+            | Expr::Tuple { elements }
+            | Expr::Struct { values: elements, .. } => {
+                elements.iter().for_each(|element| element.visit(f));
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_mut(&mut self, f: &mut dyn FnMut(&mut Expr)) {
+        f(self);
+        match self {
+            Expr::MemberAccess { object, .. } => object.visit_mut(f),
+            Expr::Index { object, index } => {
+                object.visit_mut(f);
+                if let Some(index) = index {
+                    index.visit_mut(f);
+                }
+            }
+            Expr::Call { callee, arguments, value } => {
+                callee.visit_mut(f);
+                arguments.iter_mut().for_each(|arg| arg.visit_mut(f));
+                if let Some(value) = value {
+                    value.visit_mut(f);
+                }
+            }
+            Expr::Cast { value, .. } | Expr::Unary { operand: value, .. } => value.visit_mut(f),
+            Expr::Binary { left, right, .. } => {
+                left.visit_mut(f);
+                right.visit_mut(f);
+            }
+            Expr::Ternary { condition, if_true, if_false } => {
+                condition.visit_mut(f);
+                if_true.visit_mut(f);
+                if_false.visit_mut(f);
+            }
+            Expr::New { arguments: elements, .. }
+            | Expr::Array { elements }
+            | Expr::Tuple { elements }
+            | Expr::Struct { values: elements, .. } => {
+                elements.iter_mut().for_each(|element| element.visit_mut(f));
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether the value is known without running anything: literals and
+    /// arithmetic on them, casts and `type(T).max`-style bounds.
+    pub fn is_constant(&self) -> bool {
+        let mut constant = true;
+        self.visit(&mut |expr| match expr {
+            Expr::Variable { name } if name != "type" => constant = false,
+            Expr::Call { callee, .. } if !matches!(callee.as_ref(), Expr::Variable { name } if name == "type") => constant = false,
+            Expr::New { .. } | Expr::Unsupported { .. } => constant = false,
+            _ => {}
+        });
+        constant
+    }
+
+    /// Splits `receiver.function(args)` into its parts when the receiver is a
+    /// plain variable rather than a Solidity/forge-std builtin like `vm` or
+    /// `abi`, or an address cast to a contract or interface type.
+    pub fn contract_call(&self) -> Option<(&Expr, &str, &[Expr])> {
+        let Expr::Call { callee, arguments, .. } = self else { return None };
+        let Expr::MemberAccess { object, member } = callee.as_ref() else { return None };
+        match object.as_ref() {
+            Expr::Variable { name } if !is_builtin_receiver(name) => Some((object, member, arguments)),
+            receiver if receiver.interface_cast().is_some() => Some((receiver, member, arguments)),
+            _ => None,
+        }
+    }
+
+    /// Splits `IERC20(token)` into the type and the address. Such a cast only
+    /// changes how Solidity types the address, so it calls nothing.
+    pub fn interface_cast(&self) -> Option<(&str, &Expr)> {
+        let Expr::Call { callee, arguments, .. } = self else { return None };
+        let Expr::Variable { name } = callee.as_ref() else { return None };
+        match arguments.as_slice() {
+            [address] if name.chars().next().is_some_and(char::is_uppercase) => Some((name, address)),
+            _ => None,
+        }
+    }
+}
+
+/// Names that look like call receivers but are never the contract under test.
+pub fn is_builtin_receiver(name: &str) -> bool {
+    matches!(
+        name,
+        "vm" | "abi" | "console" | "console2" | "string" | "bytes" | "block" | "msg" | "tx" | "type" | "super" | "this" | "stdstore"
+    )
+}
+
+/// Every test contract in the file, in source order, with imports resolved in
+/// the project the file belongs to.
+pub fn parse_foundry_test_file(path: &Path) -> Result<Vec<TestContract>> {
+    parse_project_test_file(path, &Project::find(path)?)
+}
+
+/// Every test contract in a test file of `project`.
+pub fn parse_project_test_file(path: &Path, project: &Project) -> Result<Vec<TestContract>> {
+    let sources = SourceGraph::load(path, project)?;
+    let (_, source_unit) = &sources.files[0];
+    let mut test_contracts = Vec::new();
+    for part in &source_unit.0 {
+        let SourceUnitPart::ContractDefinition(contract) = part else { continue };
+        if !matches!(contract.ty, solang_parser::pt::ContractTy::Contract(_)) || contract.name.is_none() {
+            continue;
+        }
+        let test_contract = extract_test_contract(contract, &sources)?;
+        if is_test_contract(&test_contract) {
+            test_contracts.push(test_contract);
+        }
+    }
+    Ok(test_contracts)
+}
+
+/// Like forge, a concrete contract is run as a test contract when it has
+/// tests or invariants, declared or inherited. Inheriting forge-std's `Test`
+/// is not required, so contracts whose bases are not found still count.
+fn is_test_contract(test_contract: &TestContract) -> bool {
+    !test_contract.test_functions.is_empty() || !test_contract.invariants.is_empty()
+}
+
+/// The test file and every file it imports, directly or indirectly, in the
+/// order they are reached. Imports are resolved with the project's
+/// remappings. forge-std and ds-test are not followed, since their cheatcodes
+/// and assertions are translated directly, and files that cannot be read or
+/// parsed are left out.
+struct SourceGraph {
+    files: Vec<(SourceFile, SourceUnit)>,
+}
+
+impl SourceGraph {
+    fn load(path: &Path, project: &Project) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .wrap_err("Failed to read Solidity test file")?;
+        let (source_unit, _) = parse(&content, 0)
+            .map_err(|e| eyre!("Failed to parse Solidity content: {:?}", e))?;
+
+        let mut graph = SourceGraph { files: Vec::new() };
+        let mut pending: VecDeque<PathBuf> = imported_paths(path, &source_unit, project).into();
+        graph.files.push((SourceFile { path: path.display().to_string(), content }, source_unit));
+        let mut seen: HashSet<PathBuf> = HashSet::from([normalize_path(path)]);
+        while let Some(path) = pending.pop_front() {
+            let path = normalize_path(&path);
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+            // A missing or broken import only loses what it declares, so the
+            // test is still generated, but without hiding why.
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(error) => {
+                    eprintln!("warning: skipping import {}: {}", path.display(), error);
+                    continue;
+                }
+            };
+            let source_unit = match parse(&content, graph.files.len()) {
+                Ok((source_unit, _)) => source_unit,
+                Err(errors) => {
+                    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+                    eprintln!("warning: skipping import {}: {}", path.display(), messages.join("; "));
+                    continue;
+                }
+            };
+            pending.extend(imported_paths(&path, &source_unit, project));
+            graph.files.push((SourceFile { path: path.display().to_string(), content }, source_unit));
+        }
+        Ok(graph)
+    }
+
+    /// The first contract called `name`, searching the test file first.
+    fn contract(&self, name: &str) -> Option<(&ContractDefinition, &SourceFile)> {
+        self.contracts().find(|(contract, _)| contract.name.as_ref().is_some_and(|id| id.name == name))
+    }
+
+    fn contracts(&self) -> impl Iterator<Item = (&ContractDefinition, &SourceFile)> {
+        self.files.iter().flat_map(|(source, source_unit)| {
+            source_unit.0.iter().filter_map(move |part| match part {
+                SourceUnitPart::ContractDefinition(contract) => Some((contract.as_ref(), source)),
+                _ => None,
+            })
+        })
+    }
+
+    /// Every constant and enum value declared in the sources, in source order.
+    /// Constants of the contracts in `inherited` are left out, since those
+    /// come in as state variables.
+    fn constants(&self, inherited: &[String]) -> Vec<Constant> {
+        let mut constants = Vec::new();
+        for (_, source_unit) in &self.files {
+            for part in &source_unit.0 {
+                match part {
+                    SourceUnitPart::VariableDefinition(var) => constants.extend(extract_constant(var, None)),
+                    SourceUnitPart::EnumDefinition(definition) => constants.extend(extract_enum(definition)),
+                    _ => {}
+                }
+            }
+        }
+        for (contract, _) in self.contracts() {
+            let name = contract.name.as_ref().map_or_else(String::new, |name| name.name.clone());
+            let mut declared = Vec::new();
+            for part in &contract.parts {
+                match part {
+                    ContractPart::VariableDefinition(var) if !inherited.contains(&name) => declared.extend(extract_constant(var, Some(&name))),
+                    ContractPart::EnumDefinition(definition) => constants.extend(extract_enum(definition)),
+                    _ => {}
+                }
+            }
+            // Sibling constants referred to by their bare name are qualified,
+            // so the value still resolves outside the contract.
+            let siblings: HashSet<String> = declared.iter().map(|constant| constant.name.clone()).collect();
+            for constant in &mut declared {
+                constant.value.visit_mut(&mut |expr| {
+                    if let Expr::Variable { name: sibling } = expr {
+                        if siblings.contains(sibling) {
+                            *expr = Expr::MemberAccess { object: Box::new(Expr::Variable { name: name.clone() }), member: sibling.clone() };
                         }
-                        _ => {}
+                    }
+                });
+            }
+            constants.extend(declared);
+        }
+        constants
+    }
+
+    /// The ABI type of every user-defined type in the sources: contracts,
+    /// interfaces and libraries are passed as `address`, enums as `uint8`,
+    /// structs as `tuple` and `type X is T` as `T`. Types declared in a
+    /// contract are listed both bare and as `Contract.Type`.
+    fn user_types(&self) -> HashMap<String, String> {
+        fn declared(part: &ContractPart) -> Option<(&Identifier, String)> {
+            match part {
+                ContractPart::EnumDefinition(definition) => Some((definition.name.as_ref()?, "uint8".to_string())),
+                ContractPart::StructDefinition(definition) => Some((definition.name.as_ref()?, "tuple".to_string())),
+                ContractPart::TypeDefinition(definition) => Some((&definition.name, definition.ty.to_string())),
+                _ => None,
+            }
+        }
+
+        let mut types = HashMap::new();
+        for (_, source_unit) in &self.files {
+            for part in &source_unit.0 {
+                let (name, type_) = match part {
+                    SourceUnitPart::EnumDefinition(definition) => (definition.name.as_ref(), "uint8".to_string()),
+                    SourceUnitPart::StructDefinition(definition) => (definition.name.as_ref(), "tuple".to_string()),
+                    SourceUnitPart::TypeDefinition(definition) => (Some(&definition.name), definition.ty.to_string()),
+                    _ => continue,
+                };
+                if let Some(name) = name {
+                    types.entry(name.name.clone()).or_insert(type_);
+                }
+            }
+        }
+        for (contract, _) in self.contracts() {
+            let Some(name) = &contract.name else { continue };
+            types.entry(name.name.clone()).or_insert_with(|| "address".to_string());
+            for (declared, type_) in contract.parts.iter().filter_map(declared) {
+                types.insert(format!("{}.{}", name.name, declared.name), type_.clone());
+                types.entry(declared.name.clone()).or_insert(type_);
+            }
+        }
+        types
+    }
+}
+
+fn imported_paths(path: &Path, source_unit: &SourceUnit, project: &Project) -> Vec<PathBuf> {
+    source_unit.0.iter()
+        .filter_map(|part| match part {
+            SourceUnitPart::ImportDirective(import) => import.literal(),
+            _ => None,
+        })
+        .map(|literal| project.resolve_import(path, &literal.string))
+        .filter(|resolved| !resolved.components().any(|component| {
+            matches!(component.as_os_str().to_str(), Some("forge-std" | "ds-test"))
+        }))
+        .collect()
+}
+
+/// A `constant` with an initializer; `scope` is the contract declaring it.
+fn extract_constant(var: &VariableDefinition, scope: Option<&str>) -> Option<Constant> {
+    if !var.attrs.iter().any(|attr| matches!(attr, solang_parser::pt::VariableAttribute::Constant(_))) {
+        return None;
+    }
+    Some(Constant {
+        scope: scope.map(str::to_string),
+        name: var.name.as_ref()?.name.clone(),
+        value: lower_expression(var.initializer.as_ref()?),
+    })
+}
+
+fn extract_enum(definition: &solang_parser::pt::EnumDefinition) -> Vec<Constant> {
+    let Some(name) = &definition.name else { return Vec::new() };
+    definition.values.iter()
+        .enumerate()
+        .filter_map(|(index, value)| Some(Constant {
+            scope: Some(name.name.clone()),
+            name: value.as_ref()?.name.clone(),
+            value: Expr::Number { value: index.to_string() },
+        }))
+        .collect()
+}
+
+/// The constants `test_contract` refers to, and the ones those refer to in
+/// turn, in source order.
+fn referenced_constants(test_contract: &TestContract, mut constants: Vec<Constant>) -> Vec<Constant> {
+    let mut names: HashSet<(Option<String>, String)> = HashSet::new();
+    let collect = |expr: &Expr, names: &mut HashSet<(Option<String>, String)>| expr.visit(&mut |expr| match expr {
+        Expr::Variable { name } => {
+            names.insert((None, name.clone()));
+        }
+        Expr::MemberAccess { object, member } => {
+            if let Expr::Variable { name } = object.as_ref() {
+                names.insert((Some(name.clone()), member.clone()));
+            }
+        }
+        _ => {}
+    });
+
+    for function in test_contract.functions() {
+        for step in function.all_steps() {
+            step.expressions().into_iter().for_each(|expr| collect(expr, &mut names));
+        }
+    }
+    test_contract.state_variables.iter().filter_map(|var| var.value.as_ref()).for_each(|expr| collect(expr, &mut names));
+
+    let is_referenced = |constant: &Constant, names: &HashSet<(Option<String>, String)>| {
+        names.contains(&(constant.scope.clone(), constant.name.clone()))
+    };
+    loop {
+        let values: Vec<Expr> = constants.iter()
+            .filter(|constant| is_referenced(constant, &names))
+            .map(|constant| constant.value.clone())
+            .collect();
+        let before = names.len();
+        values.iter().for_each(|value| collect(value, &mut names));
+        if names.len() == before {
+            break;
+        }
+    }
+    constants.retain(|constant| is_referenced(constant, &names));
+    constants
+}
+
+/// Resolves `.` and `..` without touching the file system, so the same file
+/// imported along different relative paths is only loaded once.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Extracts `contract` together with everything it inherits. Bases are
+/// extracted from the most basic one up, following the C3 linearization, so
+/// state variables come in storage order, derived functions override the ones
+/// they inherit, and `super.f(...)` can call the versions of the bases already seen.
+/// Bases that are not in the sources, like forge-std's `Test`, add nothing.
+fn extract_test_contract(contract: &ContractDefinition, sources: &SourceGraph) -> Result<TestContract> {
+    let name = contract.name.as_ref().map_or_else(String::new, |name| name.name.clone());
+    let linearization = linearize(&name, sources, &mut Vec::new())?;
+    let mut merged: Option<TestContract> = None;
+    let mut declared_in: HashMap<String, String> = HashMap::new();
+    for base in linearization.iter().rev() {
+        let Some((definition, source)) = sources.contract(base) else { continue };
+        let mut extracted = extract_contract_parts(definition, source)?;
+        let declared: Vec<String> = extracted.functions().map(|function| function.name.clone()).collect();
+        merged = Some(match merged {
+            Some(mut inherited) => {
+                resolve_super_calls(&mut extracted, &mut inherited, &declared_in);
+                inherit(inherited, extracted)
+            }
+            None => extracted,
+        });
+        declared_in.extend(declared.into_iter().map(|function| (function, base.clone())));
+    }
+
+    let mut test_contract = merged.ok_or_else(|| eyre!("No test contract found"))?;
+    test_contract.name = name;
+    mangle_overloaded_helpers(&mut test_contract);
+    mark_recursive_helpers(&mut test_contract.helpers);
+    resolve_hoax_origins(&mut test_contract);
+    test_contract.constants = referenced_constants(&test_contract, sources.constants(&linearization));
+    test_contract.user_types = sources.user_types();
+    resolve_struct_constructors(&mut test_contract);
+    Ok(test_contract)
+}
+
+/// Turns calls like `Vault.Position(a, 5)` into [`Expr::Struct`] when the
+/// callee names a struct declared in the sources. They parse as ordinary
+/// calls, so only the declarations tell them apart.
+fn resolve_struct_constructors(contract: &mut TestContract) {
+    let user_types = std::mem::take(&mut contract.user_types);
+    let mut resolve = |expr: &mut Expr| expr.visit_mut(&mut |expr| {
+        let Expr::Call { callee, arguments, .. } = expr else { return };
+        let Some(name) = type_name(callee) else { return };
+        if user_types.get(&name).is_some_and(|type_| type_ == "tuple") {
+            *expr = Expr::Struct { name, fields: Vec::new(), values: std::mem::take(arguments) };
+        }
+    });
+    for var in &mut contract.state_variables {
+        var.value.iter_mut().for_each(&mut resolve);
+    }
+    for function in contract.functions_mut() {
+        function.steps.iter_mut().for_each(|step| step.visit_mut(&mut |step| step.expressions_mut().into_iter().for_each(&mut resolve)));
+    }
+    contract.user_types = user_types;
+}
+
+/// The parts a single contract declares itself.
+fn extract_contract_parts(contract: &ContractDefinition, source: &SourceFile) -> Result<TestContract> {
+    let mut state_variables = Vec::new();
+    let mut setup = None;
+    let mut after_invariant = None;
+    let mut test_functions = Vec::new();
+    let mut invariants = Vec::new();
+    let mut helpers: Vec<TestFunction> = Vec::new();
+
+    for part in &contract.parts {
+        match part {
+            ContractPart::VariableDefinition(var) => {
+                state_variables.push(extract_state_variable(var));
+            }
+            ContractPart::FunctionDefinition(func) => {
+                if func.name.as_ref().is_some_and(|name| name.name == "setUp") {
+                    setup = Some(extract_function(func, source)?);
+                } else if func.name.as_ref().is_some_and(|name| name.name == "afterInvariant") && is_externally_visible(func) {
+                    after_invariant = Some(extract_function(func, source)?);
+                } else if is_test_function(func) {
+                    test_functions.push(extract_function(func, source)?);
+                } else if is_invariant_function(func) {
+                    invariants.push(extract_function(func, source)?);
+                } else if is_helper_function(func) {
+                    let helper = extract_helper(func, source)?;
+                    // Overloads are told apart by their number of parameters
+                    // (see `mangle_overloaded_helpers`), which is all a call
+                    // shows without type information.
+                    if helpers.iter().any(|existing| existing.name == helper.name && existing.parameters.len() == helper.parameters.len()) {
+                        eprintln!(
+                            "warning: skipping `{}` in {}: another overload takes {} parameter(s) too, so calls could not tell them apart",
+                            helper.name,
+                            source.path,
+                            helper.parameters.len()
+                        );
+                    } else {
+                        helpers.push(helper);
                     }
                 }
+            }
+            _ => {}
+        }
+    }
 
-                return Ok(TestContract {
-                    name: contract.name.as_ref().map_or_else(String::new, |name| name.name.clone()),
-                    state_variables,
-                    setup,
-                    test_functions,
+    Ok(TestContract {
+        name: contract.name.as_ref().map_or_else(String::new, |name| name.name.clone()),
+        state_variables,
+        setup,
+        test_functions,
+        invariants,
+        after_invariant,
+        helpers,
+        constants: Vec::new(),
+        user_types: HashMap::new(),
+    })
+}
+
+/// Solidity's C3 linearization of `name`, most derived first. Bases are
+/// listed most basic first in Solidity, so they are merged in reverse.
+fn linearize(name: &str, sources: &SourceGraph, visiting: &mut Vec<String>) -> Result<Vec<String>> {
+    if visiting.iter().any(|visited| visited == name) {
+        return Err(eyre!("`{}` inherits from itself", name));
+    }
+    let bases: Vec<String> = sources.contract(name)
+        .map(|(contract, _)| contract.base.iter()
+            .filter_map(|base| base.name.identifiers.last())
+            .map(|base| base.name.clone())
+            .rev()
+            .collect())
+        .unwrap_or_default();
+
+    visiting.push(name.to_string());
+    let mut sequences = bases.iter()
+        .map(|base| linearize(base, sources, visiting))
+        .collect::<Result<Vec<_>>>()?;
+    visiting.pop();
+    sequences.push(bases);
+
+    let mut linearization = vec![name.to_string()];
+    loop {
+        sequences.retain(|sequence| !sequence.is_empty());
+        if sequences.is_empty() {
+            return Ok(linearization);
+        }
+        let head = sequences.iter()
+            .map(|sequence| &sequence[0])
+            .find(|candidate| !sequences.iter().any(|sequence| sequence[1..].contains(candidate)))
+            .cloned()
+            .ok_or_else(|| eyre!("the inheritance of `{}` cannot be linearized", name))?;
+        for sequence in &mut sequences {
+            if sequence[0] == head {
+                sequence.remove(0);
+            }
+        }
+        linearization.push(head);
+    }
+}
+
+/// Adds `derived` on top of what it inherits: its state variables after the
+/// inherited ones, and its functions replacing inherited ones of the same name
+/// and number of parameters.
+fn inherit(mut inherited: TestContract, derived: TestContract) -> TestContract {
+    fn overlay(functions: &mut Vec<TestFunction>, overrides: Vec<TestFunction>) {
+        for function in overrides {
+            match functions.iter_mut().find(|existing| existing.name == function.name && existing.parameters.len() == function.parameters.len()) {
+                Some(existing) => *existing = function,
+                None => functions.push(function),
+            }
+        }
+    }
+
+    inherited.state_variables.extend(derived.state_variables);
+    inherited.setup = derived.setup.or(inherited.setup);
+    inherited.after_invariant = derived.after_invariant.or(inherited.after_invariant);
+    overlay(&mut inherited.test_functions, derived.test_functions);
+    overlay(&mut inherited.invariants, derived.invariants);
+    overlay(&mut inherited.helpers, derived.helpers);
+    inherited.name = derived.name;
+    inherited
+}
+
+/// Points `super.f(args)` at the version of `f` in `inherited`, which is
+/// kept as a helper named after the contract declaring it, `Base.f`, so the
+/// call works anywhere, including inside expressions and with a `return` in
+/// the base body. `declared_in` maps each function of `inherited` to that
+/// contract. Calls to bases that are not in the sources are left alone.
+fn resolve_super_calls(contract: &mut TestContract, inherited: &mut TestContract, declared_in: &HashMap<String, String>) {
+    let mut called: Vec<(String, String)> = Vec::new();
+    let mut base_helper = |function: &str| {
+        let base = declared_in.get(function)?;
+        let helper = format!("{}.{}", base, function);
+        if !called.iter().any(|(name, _)| name == &helper) {
+            called.push((helper.clone(), function.to_string()));
+        }
+        Some(helper)
+    };
+    for function in contract.functions_mut() {
+        function.steps.iter_mut().for_each(|step| step.visit_mut(&mut |step| {
+            if let TestStep::FunctionCall { contract: receiver @ Some(_), function, .. } = step {
+                if receiver.as_deref() == Some("super") {
+                    if let Some(helper) = base_helper(function) {
+                        *receiver = None;
+                        *function = helper;
+                    }
+                }
+            }
+            for expr in step.expressions_mut() {
+                expr.visit_mut(&mut |expr| {
+                    let Expr::Call { callee, .. } = expr else { return };
+                    let Expr::MemberAccess { object, member } = callee.as_ref() else { return };
+                    if !matches!(object.as_ref(), Expr::Variable { name } if name == "super") {
+                        return;
+                    }
+                    if let Some(helper) = base_helper(member) {
+                        **callee = Expr::Variable { name: helper };
+                    }
                 });
             }
+        }));
+    }
+
+    for (helper, function) in called {
+        if inherited.helpers.iter().any(|existing| existing.name == helper) {
+            continue;
         }
+        let Some(base) = inherited.function(&function) else { continue };
+        let mut base = base.clone();
+        base.name = helper;
+        inherited.helpers.push(base);
     }
-    Err(eyre!("No test contract found"))
 }
 
+/// forge only runs functions it can call from outside the contract.
+fn is_externally_visible(func: &FunctionDefinition) -> bool {
+    func.attributes.iter().any(|attr| matches!(attr, solang_parser::pt::FunctionAttribute::Visibility(solang_parser::pt::Visibility::Public(_) | solang_parser::pt::Visibility::External(_))))
+}
+
+/// `invariant_*` and `invariantX`, like forge.
+fn is_invariant_function(func: &FunctionDefinition) -> bool {
+    func.name.as_ref().is_some_and(|name| name.name.starts_with("invariant")) && is_externally_visible(func)
+}
+
+fn is_helper_function(func: &FunctionDefinition) -> bool {
+    matches!(func.ty, solang_parser::pt::FunctionTy::Function) && func.name.is_some() && func.body.is_some()
+}
+
+/// forge runs every public or external function whose name starts with
+/// `test`, which covers `test_`, `testFuzz_`, `testFork_` and `testFail`.
 fn is_test_function(func: &FunctionDefinition) -> bool {
-    func.name.as_ref().map_or(false, |name| 
-        name.name.starts_with("test") || 
-        name.name.starts_with("testFail") ||
-        name.name.starts_with("testRevert")
-    ) &&
-    func.attributes.iter().any(|attr| matches!(attr, solang_parser::pt::FunctionAttribute::Visibility(solang_parser::pt::Visibility::Public(_))))
+    func.name.as_ref().is_some_and(|name| name.name.starts_with("test")) && is_externally_visible(func)
 }
 
-fn extract_function(func: &FunctionDefinition) -> Result<TestFunction> {
+fn extract_function(func: &FunctionDefinition, source: &SourceFile) -> Result<TestFunction> {
     let name = func.name.as_ref()
         .map(|ident| ident.name.clone())
         .ok_or_else(|| eyre!("Function has no name"))?;
 
-    let mut steps = Vec::new();
+    let mut steps = match &func.body {
+        Some(body) => extract_body(body, source),
+        None => Vec::new(),
+    };
+    resolve_locals(&mut steps);
+    let expect_failure = name.starts_with("testFail");
+    let mut function = TestFunction { name, parameters: Vec::new(), steps, expect_failure, assumptions: Vec::new(), recursive: false, skipped: false };
+    function.parameters = func.params.iter()
+        .filter_map(|(_, param)| param.as_ref())
+        .map(|param| {
+            let name = param.name.as_ref().map_or_else(String::new, |name| name.name.clone());
+            let (min, max) = function.constant_bounds(&name).unzip();
+            TestParameter { name, type_: param.ty.to_string(), min, max }
+        })
+        .collect();
+    function.skipped = matches!(function.steps.first(), Some(TestStep::VMSkip { condition: Expr::Bool { value: true }, .. }));
+    function.assumptions = function.all_steps().into_iter()
+        .filter_map(|step| match step {
+            TestStep::Assume { source, .. } => Some(source.clone()),
+            _ => None,
+        })
+        .collect();
+    Ok(function)
+}
 
-    if let Some(Statement::Block { statements, .. }) = &func.body {
-        for stmt in statements {
-            if let Some(step) = extract_test_step(stmt) {
-                steps.push(step);
+/// A helper is extracted like a test. Named return variables become locals
+/// declared up front, returned by every bare `return` and at the end.
+fn extract_helper(func: &FunctionDefinition, source: &SourceFile) -> Result<TestFunction> {
+    let mut helper = extract_function(func, source)?;
+    let named: Vec<(String, String)> = func.returns.iter()
+        .filter_map(|(_, param)| param.as_ref())
+        .filter_map(|param| Some((param.name.as_ref()?.name.clone(), param.ty.to_string())))
+        .collect();
+    if named.is_empty() || named.len() != func.returns.len() {
+        return Ok(helper);
+    }
+
+    let returned = match named.as_slice() {
+        [(name, _)] => Expr::Variable { name: name.clone() },
+        _ => Expr::Tuple { elements: named.iter().map(|(name, _)| Expr::Variable { name: name.clone() }).collect() },
+    };
+    helper.steps.iter_mut().for_each(|step| step.visit_mut(&mut |step| {
+        if let TestStep::Return { value: value @ None, .. } = step {
+            *value = Some(returned.clone());
+        }
+    }));
+    let loc = source.location(&func.loc);
+    let declarations = named.into_iter().map(|(name, type_)| TestStep::VariableDeclaration {
+        name,
+        type_,
+        value: None,
+        mutable: true,
+        loc: loc.clone(),
+    });
+    helper.steps.splice(0..0, declarations);
+    helper.steps.push(TestStep::Return { value: Some(returned), loc });
+    Ok(helper)
+}
+
+/// JS has no overloading, so helpers overloaded by number of parameters are
+/// renamed to `name/arity`, e.g. `_mint/2`, and every local call is pointed at
+/// the overload taking as many arguments as it passes.
+fn mangle_overloaded_helpers(contract: &mut TestContract) {
+    let mut overloads: HashMap<String, usize> = HashMap::new();
+    for helper in &contract.helpers {
+        *overloads.entry(helper.name.clone()).or_default() += 1;
+    }
+    let overloaded: HashSet<String> = overloads.into_iter().filter(|(_, count)| *count > 1).map(|(name, _)| name).collect();
+    if overloaded.is_empty() {
+        return;
+    }
+
+    let mangle = |name: &mut String, arguments: usize| {
+        if overloaded.contains(name.as_str()) {
+            *name = format!("{}/{}", name, arguments);
+        }
+    };
+    for helper in &mut contract.helpers {
+        mangle(&mut helper.name, helper.parameters.len());
+    }
+    for function in contract.functions_mut() {
+        function.steps.iter_mut().for_each(|step| step.visit_mut(&mut |step| {
+            if let TestStep::FunctionCall { contract: None, function, arguments, .. } = step {
+                mangle(function, arguments.len());
+            }
+            for expr in step.expressions_mut() {
+                expr.visit_mut(&mut |expr| {
+                    if let Expr::Call { callee, arguments, .. } = expr {
+                        if let Expr::Variable { name } = callee.as_mut() {
+                            mangle(name, arguments.len());
+                        }
+                    }
+                });
             }
+        }));
+    }
+}
+
+/// Flags helpers that can reach themselves through calls to helpers, so the
+/// generator can bound their depth.
+fn mark_recursive_helpers(helpers: &mut [TestFunction]) {
+    let names: HashSet<&str> = helpers.iter().map(|helper| helper.name.as_str()).collect();
+    let calls: HashMap<&str, HashSet<String>> = helpers.iter()
+        .map(|helper| (helper.name.as_str(), helper.called_functions().into_iter().filter(|name| names.contains(name.as_str())).collect()))
+        .collect();
+
+    let recursive: HashSet<String> = helpers.iter()
+        .map(|helper| helper.name.as_str())
+        .filter(|&start| {
+            let mut seen = HashSet::new();
+            let mut pending: Vec<&str> = calls[start].iter().map(String::as_str).collect();
+            while let Some(name) = pending.pop() {
+                if name == start {
+                    return true;
+                }
+                if seen.insert(name) {
+                    pending.extend(calls[name].iter().map(String::as_str));
+                }
+            }
+            false
+        })
+        .map(str::to_string)
+        .collect();
+    for helper in helpers.iter_mut() {
+        helper.recursive = recursive.contains(&helper.name);
+    }
+}
+
+/// Marks local declarations that are assigned again as mutable and gives
+/// `delete` of a local the declared type, so it can be reset to its zero value.
+fn resolve_locals(steps: &mut [TestStep]) {
+    let mut types = HashMap::new();
+    let mut reassigned = HashSet::new();
+    for step in steps.iter_mut() {
+        step.visit_mut(&mut |step| match step {
+            TestStep::VariableDeclaration { .. } | TestStep::TupleDeclaration { .. } => {
+                types.extend(step.declared().into_iter().map(|(name, type_)| (name.clone(), type_.clone())));
+            }
+            TestStep::Assignment { target: Expr::Variable { name }, .. } => {
+                reassigned.insert(name.clone());
+            }
+            TestStep::TupleAssignment { targets, .. } => {
+                for target in targets.iter().flatten() {
+                    if let Expr::Variable { name } = target {
+                        reassigned.insert(name.clone());
+                    }
+                }
+            }
+            TestStep::Constructor { variable: Some(name), .. } => {
+                reassigned.insert(name.clone());
+            }
+            TestStep::Delete { target: Expr::Variable { name }, type_, .. } => {
+                reassigned.insert(name.clone());
+                *type_ = types.get(name).cloned();
+            }
+            _ => {}
+        });
+    }
+    for step in steps.iter_mut() {
+        step.visit_mut(&mut |step| match step {
+            TestStep::VariableDeclaration { name, mutable, .. } => {
+                *mutable = reassigned.contains(name);
+            }
+            TestStep::TupleDeclaration { variables, mutable, .. } => {
+                *mutable = variables.iter().flatten().any(|variable| reassigned.contains(&variable.name));
+            }
+            _ => {}
+        });
+    }
+}
+
+/// The steps of a block, or of the single statement used as a loop or branch body.
+fn extract_body(stmt: &Statement, source: &SourceFile) -> Vec<TestStep> {
+    match stmt {
+        Statement::Block { unchecked: false, statements, .. } => extract_steps(statements, source),
+        stmt => extract_test_step(stmt, source).into_iter().collect(),
+    }
+}
+
+/// Extracts a statement list. A `vm.expectEmit(...)` is held back and merged
+/// with the `emit` that follows it into a single `ExpectEmit` step.
+fn extract_steps(statements: &[Statement], source: &SourceFile) -> Vec<TestStep> {
+    let mut steps = Vec::new();
+    let mut pending_checks = None;
+    for stmt in statements {
+        if let Some(checks) = expect_emit_checks(stmt) {
+            pending_checks = Some(checks);
+            continue;
+        }
+        match stmt {
+            Statement::Emit(loc, Expression::FunctionCall(_, event, args)) => {
+                // An emit without `vm.expectEmit` before it only logs from the test contract.
+                let Some((check_topics, check_data, emitter)) = pending_checks.take() else { continue };
+                steps.push(TestStep::ExpectEmit {
+                    event: error_name(&lower_expression(event)).unwrap_or_else(|| event.to_string()),
+                    arguments: args.iter().map(lower_expression).collect(),
+                    check_topics,
+                    check_data,
+                    emitter,
+                    loc: source.location(loc),
+                });
+            }
+            stmt => steps.extend(extract_test_step(stmt, source)),
         }
     }
+    steps
+}
 
-    Ok(TestFunction { name, steps })
+/// The topic/data flags and emitter of a `vm.expectEmit(...)` statement. With no
+/// flags, as in `vm.expectEmit()` or `vm.expectEmit(emitter)`, everything is checked.
+fn expect_emit_checks(stmt: &Statement) -> Option<(Vec<bool>, bool, Option<Expr>)> {
+    let Statement::Expression(_, Expression::FunctionCall(_, callee, args)) = stmt else { return None };
+    let Expression::MemberAccess(_, object, member) = callee.as_ref() else { return None };
+    if !matches!(object.as_ref(), Expression::Variable(id) if id.name == "vm") || member.name != "expectEmit" {
+        return None;
+    }
+
+    let arguments: Vec<Expr> = args.iter().map(lower_expression).collect();
+    let (flags, emitter) = match arguments.len() {
+        4 | 5 => (&arguments[..4], arguments.get(4).cloned()),
+        _ => (&[][..], arguments.first().cloned()),
+    };
+    let flag = |index: usize| !matches!(flags.get(index), Some(Expr::Bool { value: false }));
+    Some(((0..3).map(flag).collect(), flag(3), emitter))
 }
 
-fn extract_test_step(stmt: &Statement) -> Option<TestStep> {
+fn extract_test_step(stmt: &Statement, source: &SourceFile) -> Option<TestStep> {
     match stmt {
-        Statement::Expression(_, expr) => extract_test_step_from_expression(expr),
+        Statement::Expression(loc, expr) => extract_test_step_from_expression(expr, source.location(loc)),
+        Statement::VariableDefinition(loc, decl, value) => Some(TestStep::VariableDeclaration {
+            name: decl.name.as_ref().map_or_else(String::new, |name| name.name.clone()),
+            type_: decl.ty.to_string(),
+            value: value.as_ref().map(lower_expression),
+            mutable: false,
+            loc: source.location(loc),
+        }),
+        Statement::Block { unchecked, statements, .. } => Some(TestStep::Block {
+            steps: extract_steps(statements, source),
+            unchecked: *unchecked,
+        }),
+        Statement::If(loc, condition, then_branch, else_branch) => Some(TestStep::If {
+            condition: lower_expression(condition),
+            then_steps: extract_body(then_branch, source),
+            else_steps: else_branch.as_ref().map(|branch| extract_body(branch, source)).unwrap_or_default(),
+            loc: source.location(loc),
+        }),
+        Statement::For(loc, init, condition, update, body) => Some(TestStep::For {
+            init: init.as_ref().and_then(|init| extract_test_step(init, source)).map(Box::new),
+            condition: condition.as_deref().map(lower_expression),
+            update: update.as_ref()
+                .and_then(|update| extract_test_step_from_expression(update, source.location(loc)))
+                .map(Box::new),
+            body: body.as_ref().map(|body| extract_body(body, source)).unwrap_or_default(),
+            loc: source.location(loc),
+        }),
+        Statement::While(loc, condition, body) => Some(TestStep::While {
+            condition: lower_expression(condition),
+            body: extract_body(body, source),
+            loc: source.location(loc),
+        }),
+        Statement::DoWhile(loc, body, condition) => Some(TestStep::DoWhile {
+            body: extract_body(body, source),
+            condition: lower_expression(condition),
+            loc: source.location(loc),
+        }),
+        Statement::Break(_) => Some(TestStep::Break),
+        Statement::Continue(_) => Some(TestStep::Continue),
+        Statement::Return(loc, value) => Some(TestStep::Return {
+            value: value.as_ref().map(lower_expression),
+            loc: source.location(loc),
+        }),
         _ => None,
     }
 }
 
-fn extract_test_step_from_expression(expr: &Expression) -> Option<TestStep> {
+fn assignment(target: &Expression, op: &str, value: Expr, loc: SourceLocation) -> Option<TestStep> {
+    Some(TestStep::Assignment {
+        target: lower_expression(target),
+        op: op.to_string(),
+        value,
+        loc,
+    })
+}
+
+/// `(uint a, , uint b) = value` declares locals, `(a, b) = value` assigns to
+/// existing ones. Solidity does not allow mixing the two in one statement.
+fn extract_tuple_assignment(parameters: &ParameterList, value: Expr, loc: SourceLocation) -> TestStep {
+    let declares = parameters.iter().any(|(_, param)| param.as_ref().is_some_and(|param| param.name.is_some()));
+    if declares {
+        let variables = parameters.iter()
+            .map(|(_, param)| {
+                let param = param.as_ref()?;
+                Some(TupleVariable { name: param.name.as_ref()?.name.clone(), type_: param.ty.to_string() })
+            })
+            .collect();
+        TestStep::TupleDeclaration { variables, value, mutable: false, loc }
+    } else {
+        let targets = parameters.iter().map(|(_, param)| param.as_ref().map(|param| lower_expression(&param.ty))).collect();
+        TestStep::TupleAssignment { targets, value, loc }
+    }
+}
+
+fn extract_test_step_from_expression(expr: &Expression, loc: SourceLocation) -> Option<TestStep> {
     match expr {
+        Expression::Assign(_, target, value) => match (target.as_ref(), lower_expression(value)) {
+            (Expression::Variable(id), Expr::New { contract, arguments }) if !contract.ends_with(']') => Some(TestStep::Constructor {
+                contract,
+                arguments,
+                variable: Some(id.name.clone()),
+                loc,
+            }),
+            (Expression::List(_, parameters), value) => Some(extract_tuple_assignment(parameters, value, loc)),
+            (_, value) => assignment(target, "=", value, loc),
+        },
+        Expression::AssignAdd(_, target, value) => assignment(target, "+=", lower_expression(value), loc),
+        Expression::AssignSubtract(_, target, value) => assignment(target, "-=", lower_expression(value), loc),
+        Expression::AssignMultiply(_, target, value) => assignment(target, "*=", lower_expression(value), loc),
+        Expression::AssignDivide(_, target, value) => assignment(target, "/=", lower_expression(value), loc),
+        Expression::AssignModulo(_, target, value) => assignment(target, "%=", lower_expression(value), loc),
+        Expression::AssignOr(_, target, value) => assignment(target, "|=", lower_expression(value), loc),
+        Expression::AssignAnd(_, target, value) => assignment(target, "&=", lower_expression(value), loc),
+        Expression::AssignXor(_, target, value) => assignment(target, "^=", lower_expression(value), loc),
+        Expression::AssignShiftLeft(_, target, value) => assignment(target, "<<=", lower_expression(value), loc),
+        Expression::AssignShiftRight(_, target, value) => assignment(target, ">>=", lower_expression(value), loc),
+        Expression::PreIncrement(_, target) | Expression::PostIncrement(_, target) => {
+            assignment(target, "+=", Expr::Number { value: "1".to_string() }, loc)
+        }
+        Expression::PreDecrement(_, target) | Expression::PostDecrement(_, target) => {
+            assignment(target, "-=", Expr::Number { value: "1".to_string() }, loc)
+        }
+        Expression::Delete(_, target) => Some(TestStep::Delete {
+            target: lower_expression(target),
+            type_: None,
+            loc,
+        }),
+        Expression::New(..) => {
+            let Expr::New { contract, arguments } = lower_expression(expr) else { return None };
+            Some(TestStep::Constructor { contract, arguments, variable: None, loc })
+        }
         Expression::FunctionCall(_, box_expr, args) => {
+            let arguments: Vec<Expr> = args.iter().map(lower_expression).collect();
             if let Expression::Variable(id) = box_expr.as_ref() {
                 let function_name = id.name.clone();
-                let arguments: Vec<String> = args.iter().map(|arg| format!("{:?}", arg)).collect();
-                println!("args: {:#?}",args);
-                println!("ARGUMENTS: {:#?}",arguments);
                 match function_name.as_str() {
-                    "vm.prank" => Some(TestStep::VMPrank(arguments[0].clone())),
-                    "vm.startPrank" => Some(TestStep::VMStartPrank(arguments[0].clone())),
-                    "vm.stopPrank" => Some(TestStep::VMStopPrank),
-                    /*"assertTrue" | "assertEq" => Some(TestStep::Assertion {
-                        assert_type: function_name,
-                        arguments,
-                    }),*/
-                    "assertTrue" | "assertEq" => Some(TestStep::Assertion {
-                        assert_type: function_name,
-                        arguments,
-                    }),
-
-
+                    "skip" if arguments.len() == 1 => Some(TestStep::Skip { seconds: arguments[0].clone(), loc }),
+                    "rewind" if arguments.len() == 1 => Some(TestStep::Rewind { seconds: arguments[0].clone(), loc }),
+                    "deal" => extract_deal(&arguments, loc),
+                    "hoax" => extract_hoax(&arguments, false, loc),
+                    "startHoax" => extract_hoax(&arguments, true, loc),
+                    name if AssertionKind::from_name(name).is_some() => extract_assertion(name, arguments, loc),
+                    name if TargetKind::from_name(name).is_some() => extract_invariant_target(name, args, loc),
                     _ => Some(TestStep::FunctionCall {
                         contract: None,
                         function: function_name,
                         arguments,
+                        loc,
                     }),
                 }
             } else if let Expression::MemberAccess(_, box_expr, member) = box_expr.as_ref() {
                 if let Expression::Variable(id) = box_expr.as_ref() {
-                    let contract = id.name.clone();
-                    let function = member.name.clone();
-                    let arguments: Vec<String> = args.iter().map(|arg| format!("{:?}", arg)).collect();
-                    println!("ARGUMENTS1: {:#?}",arguments);
-
+                    if id.name == "vm" {
+                        if let Some(step) = extract_cheatcode(&member.name, args, &loc) {
+                            return Some(step);
+                        }
+                    }
                     Some(TestStep::FunctionCall {
-                        contract: Some(contract),
-                        function,
+                        contract: Some(id.name.clone()),
+                        function: member.name.clone(),
                         arguments,
+                        loc,
                     })
                 } else {
-                    None
+                    let call = lower_expression(expr);
+                    matches!(call, Expr::Call { .. }).then_some(TestStep::ContractCall { call, loc })
                 }
             } else {
-                None
+                // `token.deposit{value: 1 ether}()`, or a call with options that
+                // are not translated, kept so the validator can report it.
+                let call = lower_expression(expr);
+                matches!(call, Expr::Call { .. } | Expr::Unsupported { .. }).then_some(TestStep::ContractCall { call, loc })
             }
         },
         _ => None,
     }
 }
 
+/// Cheatcodes the generator translates into dedicated steps. Anything else on
+/// `vm` stays a plain `FunctionCall`.
+fn extract_cheatcode(name: &str, args: &[Expression], loc: &SourceLocation) -> Option<TestStep> {
+    let arguments: Vec<Expr> = args.iter().map(lower_expression).collect();
+    let arguments = arguments.as_slice();
+    match name {
+        // `prank(sender, origin)` also sets `tx.origin`, which we cannot fake on Anvil.
+        "prank" => Some(TestStep::VMPrank { sender: arguments.first()?.clone(), loc: loc.clone() }),
+        "startPrank" | "changePrank" => Some(TestStep::VMStartPrank { sender: arguments.first()?.clone(), loc: loc.clone() }),
+        "stopPrank" => Some(TestStep::VMStopPrank),
+        "deal" => extract_deal(arguments, loc.clone()),
+        "warp" => Some(TestStep::VMWarp { timestamp: arguments.first()?.clone(), loc: loc.clone() }),
+        "roll" => Some(TestStep::VMRoll { block_number: arguments.first()?.clone(), loc: loc.clone() }),
+        // `skip(bool, reason)` is the newer overload; the reason is not shown.
+        "skip" => Some(TestStep::VMSkip { condition: arguments.first()?.clone(), loc: loc.clone() }),
+        name if name.starts_with("assert") && AssertionKind::from_name(name).is_some() => {
+            extract_assertion(name, arguments.to_vec(), loc.clone())
+        }
+        "assume" => Some(TestStep::Assume {
+            condition: arguments.first()?.clone(),
+            source: args.first()?.to_string(),
+            loc: loc.clone(),
+        }),
+        "expectRevert" => Some(TestStep::ExpectRevert {
+            expected: RevertExpectation::from_arguments(arguments),
+            loc: loc.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// `targetSelector` takes a `FuzzSelector` struct, written with named or
+/// positional fields; the other target calls take a plain address.
+fn extract_invariant_target(name: &str, args: &[Expression], loc: SourceLocation) -> Option<TestStep> {
+    let kind = TargetKind::from_name(name)?;
+    let (address, selectors) = match (kind, args.first()?) {
+        (TargetKind::Selector, Expression::NamedFunctionCall(_, _, fields)) => {
+            let field = |name: &str| fields.iter().find(|field| field.name.name == name).map(|field| lower_expression(&field.expr));
+            (field("addr")?, field("selectors"))
+        }
+        (TargetKind::Selector, Expression::FunctionCall(_, _, fields)) if fields.len() == 2 => {
+            (lower_expression(&fields[0]), Some(lower_expression(&fields[1])))
+        }
+        (_, address) => (lower_expression(address), None),
+    };
+    Some(TestStep::InvariantTarget { kind, address, selectors, loc })
+}
+
+/// Splits an assertion's arguments into operands, `decimals` for the `Decimal`
+/// variants, and the optional message. forge-std has no overload taking fewer
+/// arguments, so such calls are skipped.
+fn extract_assertion(name: &str, mut arguments: Vec<Expr>, loc: SourceLocation) -> Option<TestStep> {
+    let (kind, decimal) = AssertionKind::from_name(name)?;
+    let required = kind.operands() + usize::from(decimal);
+    if arguments.len() < required {
+        return None;
+    }
+    let message = arguments.drain(required..).next();
+    let decimals = if decimal { arguments.pop() } else { None };
+    Some(TestStep::Assertion {
+        assert_type: name.to_string(),
+        kind,
+        arguments,
+        decimals,
+        message,
+        loc,
+    })
+}
+
+/// `deal(account, amount)` sets native balance; `deal(token, account, amount)`
+/// and its `adjust` variant set an ERC20 balance. Adjusting `totalSupply` is
+/// not supported, so `adjust` is ignored.
+fn extract_deal(arguments: &[Expr], loc: SourceLocation) -> Option<TestStep> {
+    match arguments {
+        [account, amount] => Some(TestStep::VMDeal { account: account.clone(), amount: amount.clone(), loc }),
+        [token, account, amount] | [token, account, amount, _] => Some(TestStep::DealToken {
+            token: token.clone(),
+            account: account.clone(),
+            amount: amount.clone(),
+            loc,
+        }),
+        _ => None,
+    }
+}
+
+/// forge-std's balance for `hoax` without an amount: 2^128 wei.
+const HOAX_DEFAULT_AMOUNT: &str = "340282366920938463463374607431768211456";
+
+/// `hoax(sender)` funds with forge-std's default amount, `hoax(sender, amount)`
+/// and `hoax(sender, origin, amount)` with `amount`. `hoax(sender, origin)`
+/// looks like the second form until the argument types are known, see
+/// [`resolve_hoax_origins`].
+fn extract_hoax(arguments: &[Expr], start: bool, loc: SourceLocation) -> Option<TestStep> {
+    let amount = match arguments {
+        [_] => Expr::Number { value: HOAX_DEFAULT_AMOUNT.to_string() },
+        [_, amount] | [_, _, amount] => amount.clone(),
+        _ => return None,
+    };
+    Some(TestStep::Hoax { sender: arguments[0].clone(), amount, start, loc })
+}
+
+/// A two-argument `hoax` whose second argument is an address is the
+/// `hoax(sender, origin)` overload, which funds with the default amount.
+/// Addresses are told apart by their literal, an `address(...)` conversion, a
+/// `makeAddr`/`vm.addr` call, or the declared type of a state variable,
+/// parameter or local.
+fn resolve_hoax_origins(contract: &mut TestContract) {
+    let state_types: HashMap<String, String> = contract.state_variables.iter()
+        .map(|var| (var.name.clone(), var.type_.clone()))
+        .collect();
+    for function in contract.functions_mut() {
+        let mut types = state_types.clone();
+        types.extend(function.parameters.iter().map(|param| (param.name.clone(), param.type_.clone())));
+        for step in function.all_steps() {
+            types.extend(step.declared().into_iter().map(|(name, type_)| (name.clone(), type_.clone())));
+        }
+        function.steps.iter_mut().for_each(|step| step.visit_mut(&mut |step| {
+            if let TestStep::Hoax { amount, .. } = step {
+                if is_address(amount, &types) {
+                    *amount = Expr::Number { value: HOAX_DEFAULT_AMOUNT.to_string() };
+                }
+            }
+        }));
+    }
+}
+
+fn is_address(expr: &Expr, types: &HashMap<String, String>) -> bool {
+    let address_type = |type_: &str| type_ == "address" || type_ == "address payable";
+    match expr {
+        Expr::Address { .. } => true,
+        Expr::Cast { ty, .. } => address_type(ty),
+        Expr::Variable { name } => types.get(name).is_some_and(|type_| address_type(type_)),
+        Expr::Call { callee, .. } => match callee.as_ref() {
+            Expr::Variable { name } => name == "makeAddr",
+            Expr::MemberAccess { object, member } => matches!(object.as_ref(), Expr::Variable { name } if name == "vm") && member == "addr",
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Lowers a solang expression into the generator-facing [`Expr`] tree.
+pub fn lower_expression(expr: &Expression) -> Expr {
+    match expr {
+        Expression::Parenthesis(_, inner) => lower_expression(inner),
+        Expression::BoolLiteral(_, value) => Expr::Bool { value: *value },
+        Expression::NumberLiteral(_, integer, exponent, unit) => Expr::Number {
+            value: normalize_number(integer, "", exponent, unit.as_ref()),
+        },
+        Expression::RationalNumberLiteral(_, integer, fraction, exponent, unit) => Expr::Number {
+            value: normalize_number(integer, fraction, exponent, unit.as_ref()),
+        },
+        Expression::HexNumberLiteral(_, value, _) => Expr::Number { value: value.replace('_', "") },
+        Expression::StringLiteral(parts) => Expr::String {
+            value: parts.iter().map(|part| part.string.as_str()).collect(),
+        },
+        Expression::HexLiteral(parts) => Expr::Hex {
+            value: format!("0x{}", parts.iter()
+                .map(|part| part.hex.trim_start_matches("hex").trim_matches(|c| c == '"' || c == '\'').replace('_', ""))
+                .collect::<String>()),
+        },
+        Expression::AddressLiteral(_, value) => Expr::Address { value: value.clone() },
+        Expression::Variable(id) => Expr::Variable { name: id.name.clone() },
+        Expression::Type(_, ty) => Expr::Type { name: ty.to_string() },
+        Expression::MemberAccess(_, object, member) => Expr::MemberAccess {
+            object: Box::new(lower_expression(object)),
+            member: member.name.clone(),
+        },
+        Expression::ArraySubscript(_, object, index) => Expr::Index {
+            object: Box::new(lower_expression(object)),
+            index: index.as_ref().map(|index| Box::new(lower_expression(index))),
+        },
+        // `new C(args)` parses as `New(FunctionCall(C, args))`.
+        Expression::New(_, call) => match call.as_ref() {
+            Expression::FunctionCall(_, contract, args) => Expr::New {
+                contract: contract.to_string(),
+                arguments: args.iter().map(lower_expression).collect(),
+            },
+            contract => Expr::New { contract: contract.to_string(), arguments: Vec::new() },
+        },
+        Expression::FunctionCall(_, callee, args) => {
+            let arguments = args.iter().map(lower_expression).collect();
+            match callee.as_ref() {
+                Expression::Type(_, ty) if args.len() == 1 => Expr::Cast {
+                    ty: ty.to_string(),
+                    value: Box::new(lower_expression(&args[0])),
+                },
+                // `f{value: 1 ether}(args)`. Gas limits do not matter on Anvil;
+                // any other option is not translated.
+                Expression::FunctionCallBlock(_, callee, block) => {
+                    let Statement::Args(_, options) = block.as_ref() else { return Expr::Unsupported { source: expr.to_string() } };
+                    if options.iter().any(|option| option.name.name != "value" && option.name.name != "gas") {
+                        return Expr::Unsupported { source: expr.to_string() };
+                    }
+                    Expr::Call {
+                        callee: Box::new(lower_expression(callee)),
+                        arguments,
+                        value: options.iter().find(|option| option.name.name == "value").map(|option| Box::new(lower_expression(&option.expr))),
+                    }
+                }
+                _ => Expr::Call {
+                    callee: Box::new(lower_expression(callee)),
+                    arguments,
+                    value: None,
+                },
+            }
+        }
+        // Named arguments are only lowered for struct constructors, whose
+        // names are capitalized by convention; positional ones are recognized
+        // once the struct declarations are known, in `resolve_struct_constructors`.
+        Expression::NamedFunctionCall(_, callee, args) if type_name(&lower_expression(callee)).is_some_and(|name| {
+            name.rsplit('.').next().is_some_and(|name| name.starts_with(|c: char| c.is_ascii_uppercase()))
+        }) => Expr::Struct {
+            name: callee.to_string(),
+            fields: args.iter().map(|arg| arg.name.name.clone()).collect(),
+            values: args.iter().map(|arg| lower_expression(&arg.expr)).collect(),
+        },
+        Expression::Not(_, operand) => unary("!", operand),
+        Expression::BitwiseNot(_, operand) => unary("~", operand),
+        Expression::Negate(_, operand) => unary("-", operand),
+        Expression::UnaryPlus(_, operand) => lower_expression(operand),
+        Expression::Power(_, left, right) => binary("**", left, right),
+        Expression::Multiply(_, left, right) => binary("*", left, right),
+        Expression::Divide(_, left, right) => binary("/", left, right),
+        Expression::Modulo(_, left, right) => binary("%", left, right),
+        Expression::Add(_, left, right) => binary("+", left, right),
+        Expression::Subtract(_, left, right) => binary("-", left, right),
+        Expression::ShiftLeft(_, left, right) => binary("<<", left, right),
+        Expression::ShiftRight(_, left, right) => binary(">>", left, right),
+        Expression::BitwiseAnd(_, left, right) => binary("&", left, right),
+        Expression::BitwiseXor(_, left, right) => binary("^", left, right),
+        Expression::BitwiseOr(_, left, right) => binary("|", left, right),
+        Expression::Less(_, left, right) => binary("<", left, right),
+        Expression::More(_, left, right) => binary(">", left, right),
+        Expression::LessEqual(_, left, right) => binary("<=", left, right),
+        Expression::MoreEqual(_, left, right) => binary(">=", left, right),
+        Expression::Equal(_, left, right) => binary("==", left, right),
+        Expression::NotEqual(_, left, right) => binary("!=", left, right),
+        Expression::And(_, left, right) => binary("&&", left, right),
+        Expression::Or(_, left, right) => binary("||", left, right),
+        Expression::ConditionalOperator(_, condition, if_true, if_false) => Expr::Ternary {
+            condition: Box::new(lower_expression(condition)),
+            if_true: Box::new(lower_expression(if_true)),
+            if_false: Box::new(lower_expression(if_false)),
+        },
+        // `(a, b)` as a value, e.g. the right-hand side of a swap.
+        Expression::List(_, parameters) if parameters.iter().all(|(_, param)| param.as_ref().is_some_and(|param| param.name.is_none())) => Expr::Tuple {
+            elements: parameters.iter().filter_map(|(_, param)| param.as_ref()).map(|param| lower_expression(&param.ty)).collect(),
+        },
+        Expression::ArrayLiteral(_, elements) => Expr::Array {
+            elements: elements.iter().map(lower_expression).collect(),
+        },
+        _ => Expr::Unsupported { source: expr.to_string() },
+    }
+}
+
+/// `S` or `C.S` as a type name.
+fn type_name(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Variable { name } => Some(name.clone()),
+        Expr::MemberAccess { object, member } => match object.as_ref() {
+            Expr::Variable { name } => Some(format!("{}.{}", name, member)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn unary(op: &str, operand: &Expression) -> Expr {
+    Expr::Unary {
+        op: op.to_string(),
+        operand: Box::new(lower_expression(operand)),
+    }
+}
+
+fn binary(op: &str, left: &Expression, right: &Expression) -> Expr {
+    Expr::Binary {
+        op: op.to_string(),
+        left: Box::new(lower_expression(left)),
+        right: Box::new(lower_expression(right)),
+    }
+}
+
+/// Turns `<integer>.<fraction>e<exponent> <unit>` into a plain decimal integer
+/// string. Anything that does not land on an integer keeps its fractional tail.
+fn normalize_number(integer: &str, fraction: &str, exponent: &str, unit: Option<&Identifier>) -> String {
+    let integer = integer.replace('_', "");
+    let fraction = fraction.replace('_', "");
+    let mut shift: i64 = exponent.replace('_', "").parse().unwrap_or(0);
+    let mut multiplier: u64 = 1;
+    match unit.map(|unit| unit.name.as_str()) {
+        Some("ether") => shift += 18,
+        Some("gwei") => shift += 9,
+        Some("szabo") => shift += 12,
+        Some("finney") => shift += 15,
+        Some("minutes") => multiplier = 60,
+        Some("hours") => multiplier = 3_600,
+        Some("days") => multiplier = 86_400,
+        Some("weeks") => multiplier = 604_800,
+        _ => {}
+    }
+
+    // Time units are whole multiples, so `1.5 days` is scaled before the point
+    // is placed and lands on an integer.
+    let mut digits = multiply_decimal(&format!("{}{}", integer, fraction), multiplier);
+    shift -= fraction.len() as i64;
+    if shift >= 0 {
+        digits.push_str(&"0".repeat(shift as usize));
+    } else {
+        let point = digits.len() as i64 + shift;
+        if point <= 0 {
+            digits = format!("0.{}{}", "0".repeat((-point) as usize), digits);
+        } else {
+            digits.insert(point as usize, '.');
+        }
+        let trimmed = digits.trim_end_matches('0').trim_end_matches('.');
+        digits = trimmed.to_string();
+    }
+
+    let trimmed = digits.trim_start_matches('0');
+    if trimmed.is_empty() || trimmed.starts_with('.') {
+        format!("0{}", trimmed)
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn multiply_decimal(digits: &str, multiplier: u64) -> String {
+    if multiplier == 1 {
+        return digits.to_string();
+    }
+    let mut carry: u128 = 0;
+    let mut out = Vec::with_capacity(digits.len() + 8);
+    for digit in digits.bytes().rev() {
+        let product = (digit - b'0') as u128 * multiplier as u128 + carry;
+        out.push(b'0' + (product % 10) as u8);
+        carry = product / 10;
+    }
+    while carry > 0 {
+        out.push(b'0' + (carry % 10) as u8);
+        carry /= 10;
+    }
+    out.reverse();
+    String::from_utf8(out).unwrap_or_default()
+}
 
 fn extract_state_variable(var: &VariableDefinition) -> StateVariable {
     StateVariable {
         name: var.name.as_ref().map_or_else(String::new, |id| id.name.clone()),
-        type_: format!("{:?}", var.ty),
-        value: var.initializer.as_ref().map(|expr| format!("{:?}", expr)),
+        type_: var.ty.to_string(),
+        value: var.initializer.as_ref().map(lower_expression),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solang_parser::pt::Loc;
+
+    fn number(integer: &str, fraction: &str, exponent: &str, unit: Option<&str>) -> String {
+        let unit = unit.map(|name| Identifier { loc: Loc::Builtin, name: name.to_string() });
+        normalize_number(integer, fraction, exponent, unit.as_ref())
+    }
+
+    #[test]
+    fn normalize_number_applies_exponents_and_units() {
+        assert_eq!(number("1", "", "18", None), "1000000000000000000");
+        assert_eq!(number("0", "5", "", Some("ether")), "500000000000000000");
+        assert_eq!(number("1_000", "", "", None), "1000");
+        assert_eq!(number("2", "", "", Some("gwei")), "2000000000");
+    }
+
+    #[test]
+    fn normalize_number_scales_fractional_time_units() {
+        assert_eq!(number("1", "5", "", Some("days")), "129600");
+        assert_eq!(number("0", "5", "", Some("hours")), "1800");
+        assert_eq!(number("1", "25", "", Some("minutes")), "75");
+    }
+
+    #[test]
+    fn normalize_number_keeps_fractions_that_do_not_land_on_an_integer() {
+        assert_eq!(number("0", "5", "", None), "0.5");
+        assert_eq!(number("1", "", "-2", None), "0.01");
+    }
+
+    fn std_error(member: &str) -> RevertExpectation {
+        RevertExpectation::from_arguments(&[Expr::MemberAccess {
+            object: Box::new(Expr::Variable { name: "stdError".to_string() }),
+            member: member.to_string(),
+        }])
+    }
+
+    #[test]
+    fn std_errors_expect_their_panic_codes() {
+        assert!(matches!(std_error("arithmeticError"), RevertExpectation::Panic { code: 0x11 }));
+        assert!(matches!(std_error("indexOOBError"), RevertExpectation::Panic { code: 0x32 }));
+        assert!(matches!(std_error("zeroVarError"), RevertExpectation::Panic { code: 0x51 }));
+        assert!(matches!(std_error("unknownError"), RevertExpectation::Data { .. }));
+    }
+
+    fn sources(content: &str) -> SourceGraph {
+        let (source_unit, _) = parse(content, 0).expect("test source parses");
+        let source = SourceFile { path: "Test.t.sol".to_string(), content: content.to_string() };
+        SourceGraph { files: vec![(source, source_unit)] }
+    }
+
+    #[test]
+    fn linearize_follows_c3_order_through_a_diamond() {
+        let sources = sources("
+            contract A {}
+            contract B is A {}
+            contract C is A {}
+            contract D is B, C {}
+            contract E is C, B {}
+        ");
+        assert_eq!(linearize("D", &sources, &mut Vec::new()).unwrap(), ["D", "C", "B", "A"]);
+        assert_eq!(linearize("E", &sources, &mut Vec::new()).unwrap(), ["E", "B", "C", "A"]);
+    }
+
+    #[test]
+    fn linearize_rejects_inheritance_cycles() {
+        let sources = sources("
+            contract A is C {}
+            contract B is A {}
+            contract C is B {}
+        ");
+        let error = linearize("C", &sources, &mut Vec::new()).unwrap_err();
+        assert!(error.to_string().contains("inherits from itself"), "{}", error);
+    }
+
+    fn helper_call(expr: &Expr) -> Option<(&str, &[Expr])> {
+        let Expr::Call { callee, arguments, .. } = expr else { return None };
+        let Expr::Variable { name } = callee.as_ref() else { return None };
+        Some((name, arguments))
+    }
+
+    #[test]
+    fn super_calls_call_the_base_version_as_a_helper() {
+        let sources = sources("
+            contract BaseTest {
+                uint256 total;
+                function setUp() public virtual { total = 1; }
+                function _fund(uint256 amount) internal virtual { total += amount; }
+            }
+            contract FundTest is BaseTest {
+                function setUp() public override { super.setUp(); _fund(2); }
+                function _fund(uint256 amount) internal override { super._fund(amount * 2); }
+                function test_total() public {}
+            }
+        ");
+        let (contract, _) = sources.contract("FundTest").unwrap();
+        let test_contract = extract_test_contract(contract, &sources).unwrap();
+
+        let setup = test_contract.setup.as_ref().unwrap();
+        assert!(matches!(&setup.steps[0], TestStep::FunctionCall { contract: None, function, .. } if function == "BaseTest.setUp"));
+        let fund = test_contract.helpers.iter().find(|helper| helper.name == "_fund").unwrap();
+        assert!(matches!(
+            &fund.steps[..],
+            [TestStep::FunctionCall { contract: None, function, arguments, .. }]
+                if function == "BaseTest._fund" && matches!(&arguments[..], [Expr::Binary { op, .. }] if op == "*")
+        ));
+
+        let base_setup = test_contract.helpers.iter().find(|helper| helper.name == "BaseTest.setUp").unwrap();
+        assert!(matches!(&base_setup.steps[..], [TestStep::Assignment { op, .. }] if op == "="));
+        let base_fund = test_contract.helpers.iter().find(|helper| helper.name == "BaseTest._fund").unwrap();
+        assert_eq!(base_fund.parameters[0].name, "amount");
+        assert!(matches!(&base_fund.steps[..], [TestStep::Assignment { op, .. }] if op == "+="));
+    }
+
+    #[test]
+    fn super_calls_inside_expressions_reach_every_base_in_line() {
+        let sources = sources("
+            contract BaseTest {
+                uint256 total;
+                function _mintTo(address who, uint256 amt) internal virtual returns (uint256) { total += amt; return total; }
+            }
+            contract MiddleTest is BaseTest {
+                function _mintTo(address who, uint256 amt) internal virtual override returns (uint256) {
+                    return super._mintTo(who, amt + 1);
+                }
+            }
+            contract MintTest is MiddleTest {
+                function _mintTo(address who, uint256 amt) internal override returns (uint256) {
+                    return super._mintTo(who, amt * 2);
+                }
+                function test_mint() public {}
+            }
+        ");
+        let (contract, _) = sources.contract("MintTest").unwrap();
+        let test_contract = extract_test_contract(contract, &sources).unwrap();
+
+        let returned = |helper: &str| {
+            let function = test_contract.helpers.iter().find(|function| function.name == helper).unwrap();
+            let TestStep::Return { value: Some(value), .. } = &function.steps[0] else { panic!("{:?}", function.steps) };
+            helper_call(value).map(|(name, arguments)| (name.to_string(), arguments.len()))
+        };
+        assert_eq!(returned("_mintTo"), Some(("MiddleTest._mintTo".to_string(), 2)));
+        assert_eq!(returned("MiddleTest._mintTo"), Some(("BaseTest._mintTo".to_string(), 2)));
+        assert!(test_contract.helpers.iter().any(|helper| helper.name == "BaseTest._mintTo"));
+    }
+
+    #[test]
+    fn struct_constructors_become_struct_values_in_both_forms() {
+        let sources = sources("
+            contract Vault { struct Position { address owner; uint8 amount; } }
+            contract VaultTest {
+                Vault vault;
+                function test_open(address a) public {
+                    vault.open(Vault.Position({owner: a, amount: 5}));
+                    vault.open(Vault.Position(a, 5));
+                    vault.open(Vault(a));
+                }
+            }
+        ");
+        let (contract, _) = sources.contract("VaultTest").unwrap();
+        let test_contract = extract_test_contract(contract, &sources).unwrap();
+
+        let arguments: Vec<&Expr> = test_contract.test_functions[0].steps.iter()
+            .map(|step| match step {
+                TestStep::FunctionCall { arguments, .. } => &arguments[0],
+                step => panic!("{:?}", step),
+            })
+            .collect();
+        assert!(matches!(arguments[0], Expr::Struct { name, fields, values } if name == "Vault.Position" && fields == &["owner", "amount"] && values.len() == 2));
+        assert!(matches!(arguments[1], Expr::Struct { name, fields, values } if name == "Vault.Position" && fields.is_empty() && values.len() == 2));
+        assert!(matches!(arguments[2], Expr::Call { .. }));
+    }
+
+    #[test]
+    fn call_options_keep_the_value_and_reject_the_rest() {
+        let lower = |source: &str| {
+            let (unit, _) = parse(&format!("contract C {{ function f() public {{ {}; }} }}", source), 0).expect("test source parses");
+            let SourceUnitPart::ContractDefinition(contract) = &unit.0[0] else { panic!() };
+            let ContractPart::FunctionDefinition(function) = &contract.parts[0] else { panic!() };
+            let Some(Statement::Block { statements, .. }) = &function.body else { panic!() };
+            let Statement::Expression(_, expr) = &statements[0] else { panic!() };
+            lower_expression(expr)
+        };
+
+        let Expr::Call { callee, arguments, value: Some(value) } = lower("token.deposit{value: 1 ether, gas: 100}(a)") else { panic!() };
+        assert!(matches!(callee.as_ref(), Expr::MemberAccess { member, .. } if member == "deposit"));
+        assert_eq!(arguments.len(), 1);
+        assert_eq!(*value, Expr::Number { value: "1000000000000000000".to_string() });
+        assert!(matches!(lower("token.deposit(a)"), Expr::Call { value: None, .. }));
+        assert!(matches!(lower("token.deposit{salt: 1}(a)"), Expr::Unsupported { .. }));
+    }
+
+    #[test]
+    fn overloaded_helpers_are_named_and_called_by_arity() {
+        let sources = sources("
+            contract BaseTest {
+                function _sum(uint256 a) internal virtual returns (uint256) { return a; }
+            }
+            contract SumTest is BaseTest {
+                function _sum(uint256 a, uint256 b) internal returns (uint256) { return _sum(a) + b; }
+                function _twice(uint256 a) internal returns (uint256) { return a * 2; }
+                function test_sum() public { _sum(1, _sum(2)); }
+            }
+        ");
+        let (contract, _) = sources.contract("SumTest").unwrap();
+        let test_contract = extract_test_contract(contract, &sources).unwrap();
+
+        let names: Vec<&str> = test_contract.helpers.iter().map(|helper| helper.name.as_str()).collect();
+        assert_eq!(names, ["_sum/1", "_sum/2", "_twice"]);
+        let sum = test_contract.helpers.iter().find(|helper| helper.name == "_sum/2").unwrap();
+        let TestStep::Return { value: Some(Expr::Binary { left, .. }), .. } = &sum.steps[0] else { panic!("{:?}", sum.steps) };
+        assert_eq!(helper_call(left).map(|(name, _)| name), Some("_sum/1"));
+        let TestStep::FunctionCall { function, arguments, .. } = &test_contract.test_functions[0].steps[0] else { panic!() };
+        assert_eq!(function, "_sum/2");
+        assert_eq!(helper_call(&arguments[1]).map(|(name, _)| name), Some("_sum/1"));
     }
 }
@@ -1,70 +1,138 @@
-use handlebars::Handlebars;
-use serde_json::json;
-use std::fs;
-use std::path::Path;
-
-use std::fs::OpenOptions;
-use std::io::Write;
-use regex::Regex;
-use eyre::Result;
-
-use std::collections::VecDeque;
-use serde_json::Value;
-use handlebars::{ RenderErrorReason, RenderError};
-
-
-fn log_debug(message: &str) {
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("debug_log.txt")
-        .unwrap();
-    writeln!(file, "{}", message).unwrap();
-}
+use handlebars::{Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext, RenderErrorReason};
+use serde_json::{json, Value};
+
+use crate::abi_parser::{ContractAbi, ContractFunction, FunctionParameter};
+use crate::foundry_test_parser::{is_builtin_receiver, Constant, Expr, RevertExpectation, TestContract, TestStep};
+use crate::resolver::{CallResolver, Resolution};
 
-//mod foundry_test_parser;
-//use crate::foundry_test_parser::TestContract;
-use crate::foundry_test_parser::{TestContract, TestStep};
+/// Generates the component for `test_contract`, whose contract under test
+/// has `abi`. `dependencies` are the artifacts of the other contracts it
+/// deploys, which the component can deploy and call as well.
+pub fn generate_js_code(test_contract: &TestContract, abi: &ContractAbi, dependencies: &[ContractAbi]) -> Result<String, handlebars::RenderError> {
+    let resolver = CallResolver::new(test_contract, abi, dependencies);
+    let renderer = JsRenderer { resolver: &resolver, abi, dependencies };
 
-pub fn generate_js_code(test_contract: &TestContract) -> Result<String, handlebars::RenderError> {
     let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
     handlebars.register_template_string("component", include_str!("../templates/react_component.hbs"))?;
-    
+    handlebars.register_partial("steps", include_str!("../templates/steps.hbs"))?;
+
     handlebars.register_helper("capitalize", Box::new(capitalize_helper));
     handlebars.register_helper("uppercase", Box::new(uppercase_helper));
-    handlebars.register_helper("parseArg", Box::new(parse_arg_helper));
-    
-    handlebars.register_helper("parseAssertionFunction", Box::new(parse_assertion_function_helper));
-    handlebars.register_helper("parseAssertionArgs", Box::new(parse_assertion_args_helper));
-    handlebars.register_helper("generateAssertionVar", Box::new(generate_assertion_var_helper));
-    handlebars.register_helper("parseAssertion", Box::new(parse_assertion_helper));
-    handlebars.register_helper("parseFunctionCall", Box::new(parse_function_call_helper));
-    
+    handlebars.register_helper("expr", Box::new(ExprHelper(renderer)));
+    handlebars.register_helper("statement", Box::new(StatementHelper(renderer)));
+    handlebars.register_helper("deploy", Box::new(DeployHelper(renderer)));
+    handlebars.register_helper("revert", Box::new(RevertHelper(renderer)));
+    handlebars.register_helper("assertion", Box::new(AssertionHelper(renderer)));
     handlebars.register_helper("raw", Box::new(raw_helper));
-    handlebars.register_helper("json", Box::new(json_helper));  // Add this line
+    handlebars.register_helper("json", Box::new(json_helper));
 
-
-    let contract_functions = extract_contract_functions(test_contract);
+    let called_functions = extract_contract_functions(test_contract, &abi.functions);
+    let mut contract_functions: Vec<String> = called_functions.iter().map(|function| function.human_readable()).collect();
+    contract_functions.sort();
+    contract_functions.dedup();
 
     let data = json!({
         "contractName": test_contract.name,
-        "stateVariables": test_contract.state_variables,
+        "stateVariables": test_contract.state_variables.iter()
+            .map(|var| json!({ "name": var.name, "value": renderer.render_state_initializer(&var.type_, var.value.as_ref()) }))
+            .collect::<Vec<_>>(),
+        "constants": renderer.render_constants(&test_contract.constants),
+        "bytecodes": std::iter::once(abi).chain(dependencies)
+            .filter_map(|abi| abi.contract_name.as_ref().zip(abi.bytecode.as_ref()))
+            .map(|(contract, bytecode)| json!({ "contract": contract, "bytecode": bytecode }))
+            .collect::<Vec<_>>(),
+        "dependencies": dependencies.iter()
+            .filter_map(|dependency| Some(json!({
+                "contract": dependency.contract_name.as_ref()?,
+                "constructor": dependency.constructor.as_ref().map(|constructor| constructor.human_readable()),
+                "functions": dependency.functions.iter().map(|function| function.human_readable()).collect::<Vec<_>>(),
+            })))
+            .collect::<Vec<_>>(),
         "setupFunction": test_contract.setup,
         "testFunctions": test_contract.test_functions,
+        "invariants": test_contract.invariants,
+        "afterInvariant": test_contract.after_invariant,
+        "helpers": test_contract.helpers,
+        "recursiveHelpers": test_contract.helpers.iter().any(|helper| helper.recursive),
         "contractFunctions": contract_functions,
+        "structTypes": extract_struct_types(&called_functions, &renderer.constructed_structs(test_contract)),
+        "contractEvents": unique(std::iter::once(abi).chain(dependencies).flat_map(|abi| &abi.events).map(|event| event.human_readable())),
+        "contractErrors": unique(std::iter::once(abi).chain(dependencies).flat_map(|abi| &abi.errors).map(|error| error.human_readable())),
+        "contractConstructor": abi.constructor.as_ref().map(|constructor| constructor.human_readable()),
+        "contractFallback": abi.fallback.as_ref().map(|fallback| fallback.human_readable()),
+        "contractReceive": abi.receive.as_ref().map(|receive| receive.human_readable()),
     });
 
     handlebars.render("component", &data)
 }
 
+/// The events and errors of all deployed contracts go into one ABI, so each
+/// signature is kept once, in order of first appearance.
+fn unique(signatures: impl Iterator<Item = String>) -> Vec<String> {
+    let mut unique = Vec::new();
+    for signature in signatures {
+        if !unique.contains(&signature) {
+            unique.push(signature);
+        }
+    }
+    unique
+}
 
+/// A generated component as listed in the project index. `file` is the test
+/// file relative to the project root and `module` the component's path
+/// relative to the index, without extension.
+pub struct IndexEntry<'a> {
+    pub test_contract: &'a TestContract,
+    pub file: String,
+    pub module: String,
+    pub target: Option<String>,
+}
+
+/// The index module of a project run: it exports every component along with
+/// a catalog of the tests each one runs.
+pub fn generate_index(entries: &[IndexEntry]) -> Result<String, handlebars::RenderError> {
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
+    handlebars.register_template_string("index", include_str!("../templates/index.hbs"))?;
+
+    let plain = |entry: &IndexEntry| format!("{}Component", entry.test_contract.name);
+    let entries: Vec<Value> = entries.iter()
+        .map(|entry| json!({
+            "contract": entry.test_contract.name,
+            "component": match entries.iter().filter(|other| plain(other) == plain(entry)).count() {
+                1 => plain(entry),
+                _ => module_identifier(&entry.module),
+            },
+            "file": entry.file,
+            "module": entry.module,
+            "target": entry.target,
+            "tests": entry.test_contract.test_functions.iter()
+                .map(|test| json!({ "name": test.name, "fuzz": !test.parameters.is_empty(), "expectFailure": test.expect_failure, "skipped": test.skipped }))
+                .collect::<Vec<_>>(),
+            "invariants": entry.test_contract.invariants.iter().map(|invariant| &invariant.name).collect::<Vec<_>>(),
+        }))
+        .collect();
+    handlebars.render("index", &json!({ "entries": entries }))
+}
+
+/// `unit/TokenTestComponent` as `unit_TokenTestComponent`, for components
+/// whose contract name alone is not unique in the project.
+fn module_identifier(module: &str) -> String {
+    let identifier: String = module.chars().map(|c| if c.is_ascii_alphanumeric() || c == '$' { c } else { '_' }).collect();
+    if identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", identifier)
+    } else {
+        identifier
+    }
+}
 
 fn raw_helper(
     h: &handlebars::Helper, _: &handlebars::Handlebars, _: &handlebars::Context, _: &mut handlebars::RenderContext, out: &mut dyn handlebars::Output
 ) -> handlebars::HelperResult {
-    let param = h.param(0).ok_or_else(|| RenderError::new("Param 0 is required for raw helper"))?;
+    let param = h.param(0).ok_or(RenderErrorReason::ParamNotFoundForIndex("raw", 0))?;
     let value = param.value();
-    
+
     out.write("{")?;
     match value {
         Value::String(s) => out.write(s.as_str())?,
@@ -76,9 +144,7 @@ fn raw_helper(
 
 fn capitalize_helper(h: &handlebars::Helper, _: &handlebars::Handlebars, _: &handlebars::Context, _: &mut handlebars::RenderContext, out: &mut dyn handlebars::Output) -> handlebars::HelperResult {
     let param = h.param(0).and_then(|v| v.value().as_str()).unwrap_or("");
-    log_debug(&format!("PARAM: {}", param));
-    let capitalized = param.chars().next().map(|c| c.to_uppercase().collect::<String>() + &param[1..]).unwrap_or_default();
-    log_debug(&format!("PARAM_CAP: {}", capitalized));
+    let capitalized = param.chars().next().map(|c| c.to_uppercase().collect::<String>() + &param[c.len_utf8()..]).unwrap_or_default();
     out.write(&capitalized)?;
     Ok(())
 }
@@ -89,820 +155,640 @@ fn uppercase_helper(h: &handlebars::Helper, _: &handlebars::Handlebars, _: &hand
     Ok(())
 }
 
-fn parse_assertion_helper(h: &handlebars::Helper, _: &handlebars::Handlebars, _: &handlebars::Context, _: &mut handlebars::RenderContext, out: &mut dyn handlebars::Output) -> handlebars::HelperResult {
-    let arg = h.param(0).and_then(|v| v.value().as_str()).unwrap_or("");
-    let parsed = parse_assertion(arg);
-    out.write(&parsed)?;
-    Ok(())
-}
+/// Renders a serialized [`Expr`] as JavaScript.
+struct ExprHelper<'a>(JsRenderer<'a>);
 
-
-fn parse_function_call(map: &serde_json::Map<String, Value>) -> String {
-
-println!("function_call: {:#?}",map);
-
-    let function = parse_value(map.get("function").unwrap_or(&Value::Null));
-    let empty_vec = Vec::new();
-    let arguments = map.get("arguments").and_then(|a| a.as_array()).unwrap_or(&empty_vec);
-    let parsed_args: Vec<String> = arguments.iter().map(parse_value).collect();
-    format!("{}({})", function, parsed_args.join(", "))
-
-
-}
-
-
-fn parse_member_access(map: &serde_json::Map<String, Value>) -> String {
-    let object = parse_value(map.get("object").unwrap_or(&Value::Null));
-    let member = parse_value(map.get("member").unwrap_or(&Value::Null));
-    format!("{}.{}", object, member)
-}
-
-
-
-
-
-
-fn parse_variable(map: &serde_json::Map<String, Value>) -> String {
-    map.get("name")
-       .and_then(|n| n.as_object())
-       .and_then(|o| o.get("name"))
-       .and_then(|n| n.as_str())
-       .unwrap_or("null")
-       .to_string()
-}
-
-
-fn parse_number_literal(map: &serde_json::Map<String, Value>) -> String {
-    let value = map.get("value").and_then(|v| v.as_str()).unwrap_or("0");
-    let subdenomination = map.get("subdenomination").and_then(|s| s.as_str()).unwrap_or("");
-    format!("BigInt({}{})", value, subdenomination)
-}
-
-fn extract_variable_name(var: &str) -> String {
-    if let Some(start) = var.rfind("name: \"") {
-        let start = start + 7;
-        if let Some(end) = var[start..].find('"') {
-            return var[start..start+end].to_string();
-        }
+impl HelperDef for ExprHelper<'_> {
+    fn call<'reg: 'rc, 'rc>(&self, h: &Helper<'rc>, _: &'reg Handlebars<'reg>, _: &'rc Context, _: &mut RenderContext<'reg, 'rc>, out: &mut dyn Output) -> HelperResult {
+        let param = h.param(0).ok_or(RenderErrorReason::ParamNotFoundForIndex("expr", 0))?;
+        let expr: Expr = serde_json::from_value(param.value().clone())
+            .map_err(|_| RenderErrorReason::InvalidParamType("Expr"))?;
+        out.write(&self.0.render_expr(&expr))?;
+        Ok(())
     }
-    "unknownVariable".to_string()
 }
 
-fn parse_identifier(map: &serde_json::Map<String, Value>) -> String {
-    map.get("name")
-       .and_then(|n| n.as_str())
-       .unwrap_or("null")
-       .to_string()
-}
+/// Renders a simple step (declaration, assignment, `delete` or call) as a
+/// single JS statement without the trailing semicolon, so the same output
+/// works on its own line and inside a `for (...)` header.
+struct StatementHelper<'a>(JsRenderer<'a>);
 
-fn extract_number_literal(num: &str) -> String {
-    if let Some(start) = num.rfind(", \"") {
-        let start = start + 3;
-        if let Some(end) = num[start..].find('"') {
-            return format!("BigInt({}e18)", &num[start..start+end]);
-        }
+impl HelperDef for StatementHelper<'_> {
+    fn call<'reg: 'rc, 'rc>(&self, h: &Helper<'rc>, _: &'reg Handlebars<'reg>, _: &'rc Context, _: &mut RenderContext<'reg, 'rc>, out: &mut dyn Output) -> HelperResult {
+        let param = h.param(0).ok_or(RenderErrorReason::ParamNotFoundForIndex("statement", 0))?;
+        let step: TestStep = serde_json::from_value(param.value().clone())
+            .map_err(|_| RenderErrorReason::InvalidParamType("TestStep"))?;
+        let statement = self.0.render_statement(&step).ok_or(RenderErrorReason::InvalidParamType("simple TestStep"))?;
+        out.write(&statement)?;
+        Ok(())
     }
-    "BigInt(0)".to_string()
 }
 
-fn parse_arg_helper(h: &handlebars::Helper, _: &handlebars::Handlebars, _: &handlebars::Context, _: &mut handlebars::RenderContext, out: &mut dyn handlebars::Output) -> handlebars::HelperResult {
-    let param = h.param(0).ok_or_else(|| RenderError::new("Param 0 is required for parseArg helper"))?;
-    let input = param.value().to_string();
-    log_debug(&format!("parse_arg_helper input: {}", input));
-    
-    let parsed = parse_ast_string(&input);
-    log_debug(&format!("parse_arg_helper parsed: {}", parsed));
-
-    out.write(&parsed)?;
-    Ok(())
-}
+/// Renders a `Constructor` step as a deployment, assigning the new address to
+/// the state variable it was written to in Solidity. Without bytecode the
+/// step becomes a comment and the variable keeps its default address.
+struct DeployHelper<'a>(JsRenderer<'a>);
 
-fn parse_ast_string(input: &str) -> String {
-    let trimmed = input.trim_matches('"');
-    if trimmed.starts_with("FunctionCall(") {
-        parse_function_call_string(trimmed)
-    } else if trimmed.starts_with("MemberAccess(") {
-        parse_member_access_string(trimmed)
-    } else if trimmed.starts_with("Variable(") {
-        parse_variable_string(trimmed)
-    } else if trimmed.starts_with("NumberLiteral(") {
-        parse_number_literal_string(trimmed)
-    } else if trimmed.starts_with("Identifier(") {
-        parse_identifier_string(trimmed)
-    } else {
-        log_debug(&format!("Unknown AST node type: {}", trimmed));
-        trimmed.to_string()
-    }
-}
+impl HelperDef for DeployHelper<'_> {
+    fn call<'reg: 'rc, 'rc>(&self, h: &Helper<'rc>, _: &'reg Handlebars<'reg>, _: &'rc Context, _: &mut RenderContext<'reg, 'rc>, out: &mut dyn Output) -> HelperResult {
+        let param = h.param(0).ok_or(RenderErrorReason::ParamNotFoundForIndex("deploy", 0))?;
+        let step: TestStep = serde_json::from_value(param.value().clone())
+            .map_err(|_| RenderErrorReason::InvalidParamType("TestStep"))?;
+        let TestStep::Constructor { contract, arguments, variable, .. } = step else {
+            return Err(RenderErrorReason::InvalidParamType("Constructor").into());
+        };
 
-fn parse_identifier_string(input: &str) -> String {
-    if let Some(start) = input.rfind("name: \"") {
-        let start = start + 7;
-        if let Some(end) = input[start..].find('"') {
-            return input[start..start+end].to_string();
+        if !self.0.can_deploy(&contract) {
+            let source = self.0.render_list(&arguments);
+            out.write(&format!("// new {}({}) needs the artifact bytecode; keeping the default address", contract, source))?;
+            return Ok(());
         }
+        let deployment = self.0.render_expr(&Expr::New { contract, arguments });
+        match variable {
+            Some(name) => out.write(&format!("{} = {};", self.0.render_expr(&Expr::Variable { name }), deployment))?,
+            None => out.write(&format!("{};", deployment))?,
+        }
+        Ok(())
     }
-    log_debug(&format!("Failed to parse identifier: {}", input));
-    "unknownIdentifier".to_string()
 }
 
+/// Renders a `vm.expectRevert` expectation as the object the component's
+/// `expectRevert` takes: `{ reason }`, `{ error, args }` or `{ data }`.
+struct RevertHelper<'a>(JsRenderer<'a>);
 
-    fn parse_function_call_helper(h: &handlebars::Helper, _: &handlebars::Handlebars, _: &handlebars::Context, _: &mut handlebars::RenderContext, out: &mut dyn handlebars::Output) -> handlebars::HelperResult {
-        let param = h.param(0).ok_or_else(|| RenderError::new("Param 0 is required for parseFunctionCall helper"))?;
-        let input = param.value().to_string();
-        log_debug(&format!("parse_function_call_helper input: {}", input));
-        
-        let parsed = parse_ast_string(&input);
-        log_debug(&format!("parse_function_call_helper parsed: {}", parsed));
-    
-        out.write(&parsed)?;
+impl HelperDef for RevertHelper<'_> {
+    fn call<'reg: 'rc, 'rc>(&self, h: &Helper<'rc>, _: &'reg Handlebars<'reg>, _: &'rc Context, _: &mut RenderContext<'reg, 'rc>, out: &mut dyn Output) -> HelperResult {
+        let param = h.param(0).ok_or(RenderErrorReason::ParamNotFoundForIndex("revert", 0))?;
+        let expected: RevertExpectation = serde_json::from_value(param.value().clone())
+            .map_err(|_| RenderErrorReason::InvalidParamType("RevertExpectation"))?;
+        let object = match expected {
+            RevertExpectation::Any => "{}".to_string(),
+            RevertExpectation::Reason { reason } => format!("{{ reason: {} }}", Value::String(reason)),
+            RevertExpectation::CustomError { name, arguments: None } => format!("{{ error: \"{}\" }}", name),
+            RevertExpectation::CustomError { name, arguments: Some(arguments) } => {
+                format!("{{ error: \"{}\", args: [{}] }}", name, self.0.render_list(&arguments))
+            }
+            // viem decodes panics as the builtin `Panic(uint256)` error.
+            RevertExpectation::Panic { code } => format!("{{ error: \"Panic\", args: [{}n] }}", code),
+            RevertExpectation::Data { data } => format!("{{ data: {} }}", self.0.render_expr(&data)),
+        };
+        out.write(&object)?;
         Ok(())
     }
-/*
-    fn parse_function_call_string(input: &str) -> String {
-        log_debug(&format!("parse_function_call_string input: {}", input));
+}
 
-        let inner = input.trim_start_matches("FunctionCall(").trim_end_matches(')');
-        let parts: Vec<&str> = split_top_level(inner, ", ");
-        log_debug(&format!("parse_function_call_string parts: {:#?}", parts));
+/// Renders an `Assertion` step as a call to the component's `check`, e.g.
+/// `check("ApproxEqAbs", [a, b, delta], { message: "...", decimals: 18n })`.
+struct AssertionHelper<'a>(JsRenderer<'a>);
 
-        let result = if parts.len() >= 3 {
-            let member_access = parse_ast_string(parts[1]);
-            let args = parse_arguments_string(parts[2]);
-            format!("{}({})", member_access, args)
-        } else {
-            "null".to_string()
+impl HelperDef for AssertionHelper<'_> {
+    fn call<'reg: 'rc, 'rc>(&self, h: &Helper<'rc>, _: &'reg Handlebars<'reg>, _: &'rc Context, _: &mut RenderContext<'reg, 'rc>, out: &mut dyn Output) -> HelperResult {
+        let param = h.param(0).ok_or(RenderErrorReason::ParamNotFoundForIndex("assertion", 0))?;
+        let step: TestStep = serde_json::from_value(param.value().clone())
+            .map_err(|_| RenderErrorReason::InvalidParamType("TestStep"))?;
+        let TestStep::Assertion { kind, arguments, decimals, message, .. } = step else {
+            return Err(RenderErrorReason::InvalidParamType("Assertion").into());
         };
-        log_debug(&format!("parse_function_call_string output: {}", result));
-
-        result
-    }
 
-
-fn parse_function_call_string(input: &str) -> String {
-    let parts: Vec<&str> = split_top_level(input, ", ");
-    log_debug(&format!("Failed to parse function call / parts: {:#?}", parts));
-
-    if parts.len() >= 3 {
-        let function = parse_ast_string(parts[1]);
-        let args = parse_arguments_string(parts[2]);
-        if function.contains('.') {
-            format!("{}({})", function, args)
+        let options: Vec<String> = message.iter()
+            .map(|message| format!("message: {}", self.0.render_expr(message)))
+            .chain(decimals.iter().map(|decimals| format!("decimals: {}", self.0.render_expr(decimals))))
+            .collect();
+        let kind = serde_json::to_value(kind).map_err(|e| RenderErrorReason::Other(e.to_string()))?;
+        if options.is_empty() {
+            out.write(&format!("check({}, [{}])", kind, self.0.render_list(&arguments)))?;
         } else {
-            format!("contract.write.{}({})", function, args)
+            out.write(&format!("check({}, [{}], {{ {} }})", kind, self.0.render_list(&arguments), options.join(", ")))?;
         }
-    } else {
-        log_debug(&format!("Failed to parse function call: {}", input));
-        "unknownFunction11()".to_string()
-    }
-}
-*/
-/*
-fn parse_function_call_string(input: &str) -> String {
-    let content = input.trim_start_matches("FunctionCall(").trim_end_matches(')');
-    let parts: Vec<&str> = split_top_level(content, ", ");
-    
-    if parts.len() >= 3 {
-        let _file_info = parts[0]; // We're ignoring this for now
-        let function = parse_ast_string(parts[1]);
-        let args = parse_arguments_string(parts[2]);
-        format!("{}({})", function, args)
-    } else {
-        log_debug(&format!("Unexpected FunctionCall format: {}", input));
-        "unknownFunction()".to_string()
-    }
-}
-*/
-fn parse_function_call_string(input: &str) -> String {
-    let content = input.trim_start_matches("FunctionCall(").trim_end_matches(')');
-    let parts: Vec<&str> = split_top_level(content, ", ");
-    
-    if parts.len() >= 3 {
-        let _file_info = parts[0]; // We're ignoring this for now
-        let function = parse_ast_string(parts[1]);
-        let args = parse_arguments_string(parts[2]);
-        format!("{}({})", function, args)
-    } else {
-        log_debug(&format!("Unexpected FunctionCall format: {}", input));
-        "unknownFunction()".to_string()
+        Ok(())
     }
 }
 
-
-
-/*
-fn parse_function_call_string(input: &str) -> String {
-        // Extract the member access part
-        let member_access_re = Regex::new(r#"MemberAccess\(.*?, Variable\(Identifier \{ .*? name: "(\w+)" \}\), Identifier \{ .*? name: "(\w+)" \}\)"#).unwrap();
-        let member_access = member_access_re.captures(input)
-            .map(|cap| format!("{}.{}", &cap[1], &cap[2]))
-            .unwrap_or_else(|| "unknownObject.unknownMethod000000".to_string());
-    
-        // Extract the arguments
-        let args_re = Regex::new(r"\[(.*?)\]").unwrap();
-        let args = args_re.captures(input)
-            .and_then(|cap| Some(cap[1].to_string()))
-            .unwrap_or_else(String::new);
-    
-        // Parse individual arguments
-        let parsed_args: Vec<String> = args.split(", ")
-            .map(|arg| {
-                if arg.contains("Variable") {
-                    let var_re = Regex::new(r#"name: "(\w+)""#).unwrap();
-                    var_re.captures(arg)
-                        .map(|cap| cap[1].to_string())
-                        .unwrap_or_else(|| "unknownVar".to_string())
-                } else if arg.contains("NumberLiteral") {
-                    let num_re = Regex::new(r#"NumberLiteral\(.*?, "(\d+)", "(\d+)""#).unwrap();
-                    num_re.captures(arg)
-                        .map(|cap| format!("parseEther(\"{}\")", &cap[1]))
-                        .unwrap_or_else(|| "parseEther(\"0\")".to_string())
-                } else {
-                    "unknownArg".to_string()
-                }
-            })
-            .collect();
-    
-        // Combine everything
-        format!("{}([{}]);", member_access, parsed_args.join(", "))
-    }
-    */
-    /*
-    fn parse_member_access_string(input: &str) -> String {
-        log_debug(&format!("parse_member_access_string input: {}", input));
-
-        let inner = input.trim_start_matches("MemberAccess(").trim_end_matches(')');
-        let parts: Vec<&str> = split_top_level(inner, ", ");
-        log_debug(&format!("parse_member_access_string parts: {:?}", parts));
-
-        let result = if parts.len() >= 3 {
-            let object = parse_ast_string(parts[1]);
-            let member = parse_ast_string(parts[2]);
-            format!("{}.{}", object, member)
-        } else {
-            "null".to_string()
-        };
-        log_debug(&format!("parse_member_access_string output: {}", result));
-
-        result
-    }
-
-*/
-/*
-
-fn parse_member_access_string(input: &str) -> String {
-    let parts: Vec<&str> = split_top_level(input, ", ");
-    if parts.len() >= 3 {
-        let object = parse_ast_string(parts[1]);
-        let member = parse_ast_string(parts[2]);
-        format!("{}.{}", object, member)
-    } else {
-        log_debug(&format!("Failed to parse member access: {}", input));
-
-        "unknownObject.unknownMember2342342".to_string()
-    }
-}
-*/
-
-fn parse_member_access_string(input: &str) -> String {
-    let content = input.trim_start_matches("MemberAccess(").trim_end_matches(')');
-    let parts: Vec<&str> = split_top_level(content, ", ");
-    
-    if parts.len() >= 3 {
-        let _file_info = parts[0]; // We're ignoring this for now
-        let object = parse_ast_string(parts[1]);
-        let member = parse_ast_string(parts[2]);
-        format!("{}.{}", object, member)
-    } else {
-        "unknownObject.unknownMember".to_string()
-    }
+fn json_helper(h: &handlebars::Helper, _: &handlebars::Handlebars, _: &handlebars::Context, _: &mut handlebars::RenderContext, out: &mut dyn handlebars::Output) -> handlebars::HelperResult {
+    let param = h.param(0).ok_or(RenderErrorReason::ParamNotFoundForIndex("json", 0))?;
+    let json_str = serde_json::to_string(param.value())
+        .map_err(|e| RenderErrorReason::Other(format!("Failed to serialize to JSON: {}", e)))?;
+    out.write(&json_str)?;
+    Ok(())
 }
 
-
-    
-/*
-    fn parse_variable_string(input: &str) -> String {
-        log_debug(&format!("parse_variable_string input: {}", input));
-
-        let name_start = input.rfind("name: \"").map(|i| i + 7).unwrap_or(0);
-        let name_end = input[name_start..].find('"').map(|i| i + name_start).unwrap_or(input.len());
-        let result = input[name_start..name_end].to_string();
-        log_debug(&format!("parse_variable_string output: {:?}", result));
-
-        result
-    }
-
-
-fn parse_variable_string(input: &str) -> String {
-    
-    if let Some(start) = input.rfind("name: \\\"") {
-        let start = start + 8; // 7 for "name: \"" plus 1 for the extra backslash
-        if let Some(end) = input[start..].find("\\\"") {
-            return input[start..start+end].to_string();
+/// Translates expressions into the JavaScript the component evaluates.
+/// Integers become bigint literals so arithmetic matches Solidity semantics.
+#[derive(Clone, Copy)]
+struct JsRenderer<'a> {
+    resolver: &'a CallResolver<'a>,
+    abi: &'a ContractAbi,
+    dependencies: &'a [ContractAbi],
+}
+
+impl JsRenderer<'_> {
+    fn render_expr(&self, expr: &Expr) -> String {
+        match expr {
+            Expr::Number { value } if value.contains('.') => value.clone(),
+            Expr::Number { value } => format!("{}n", value),
+            Expr::Bool { value } => value.to_string(),
+            Expr::String { value } => Value::String(value.clone()).to_string(),
+            Expr::Hex { value } | Expr::Address { value } => format!("\"{}\"", value),
+            Expr::Variable { name } if self.resolver.is_state_variable(name) => format!("state.{}", name),
+            Expr::Variable { name } => name.clone(),
+            Expr::Type { name } => name.clone(),
+            Expr::MemberAccess { object, member } => match (object.as_ref(), member.as_str()) {
+                (Expr::Variable { name }, "timestamp" | "number") if name == "block" => {
+                    format!("(await latestBlock()).{}", member)
+                }
+                // `Token.transfer.selector` or `Err.selector`.
+                (Expr::MemberAccess { member: name, .. }, "selector") => format!("selectorOf(\"{}\")", name),
+                (Expr::Variable { name }, "selector") if !self.resolver.is_state_variable(name) => {
+                    format!("selectorOf(\"{}\")", name)
+                }
+                // In forge the test contract is called by the default sender.
+                (Expr::Variable { name }, "sender" | "origin") if name == "msg" || name == "tx" => "DEFAULT_SENDER".to_string(),
+                (Expr::Variable { name }, "value") if name == "msg" => "0n".to_string(),
+                (Expr::Variable { name }, "chainid") if name == "block" => "BigInt(anvil.id)".to_string(),
+                (Expr::Variable { name }, _) if name == "msg" || name == "tx" || name == "block" => {
+                    format!("undefined /* {}.{} is not supported */", name, member)
+                }
+                _ => match type_bound(object, member) {
+                    Some(bound) => bound,
+                    None => format!("{}.{}", self.render_expr(object), member),
+                },
+            },
+            Expr::Index { object, index } => format!(
+                "{}[{}]",
+                self.render_expr(object),
+                index.as_ref().map(|index| self.render_expr(index)).unwrap_or_default()
+            ),
+            Expr::Call { callee, arguments, value } => match (expr.contract_call(), callee.as_ref()) {
+                (_, Expr::MemberAccess { object, member }) if self.resolver.is_address_call(object, member) => {
+                    self.render_address_call(object, member, arguments, value.as_deref())
+                }
+                (Some((receiver, function, arguments)), _) => self.render_contract_call(receiver, function, arguments, value.as_deref()),
+                (None, Expr::Variable { name }) if self.resolver.is_helper(name) => {
+                    format!("(await helpers[{}]({}))", Value::String(name.clone()), self.render_list(arguments))
+                }
+                // `super.f(...)` on a base that is not in the sources.
+                (None, Expr::MemberAccess { object, member }) if matches!(object.as_ref(), Expr::Variable { name } if name == "super") => {
+                    format!("undefined /* {}(...) of a base that is not in the sources */", member)
+                }
+                // `this.f(...)` calls a helper of the test contract from outside.
+                (None, Expr::MemberAccess { object, member }) if matches!(object.as_ref(), Expr::Variable { name } if name == "this") && self.resolver.is_helper(member) => {
+                    format!("(await helpers[{}]({}))", Value::String(member.clone()), self.render_list(arguments))
+                }
+                (None, callee) if builtin_name(callee).is_some() => {
+                    let name = builtin_name(callee).unwrap_or_default();
+                    self.render_builtin(&name, arguments)
+                        .unwrap_or_else(|| format!("undefined /* {}(...) is not supported */", name))
+                }
+                // `IERC20(token)` is just the address.
+                (None, Expr::Variable { name }) if expr.interface_cast().is_some() && self.resolver.is_contract_type(name) => {
+                    self.render_expr(&arguments[0])
+                }
+                (None, _) => format!("{}({})", self.render_expr(callee), self.render_list(arguments)),
+            },
+            Expr::Cast { ty, value } => self.render_cast(ty, value),
+            // `new bytes4[](n)`: a memory array of `n` zero values.
+            Expr::New { contract, arguments } if contract.ends_with("[]") => format!(
+                "Array.from({{ length: Number({}) }}, () => {})",
+                arguments.first().map(|length| self.render_expr(length)).unwrap_or_else(|| "0".to_string()),
+                zero_value(&contract[..contract.len() - 2])
+            ),
+            Expr::New { contract, arguments } if self.can_deploy(contract) => {
+                format!("(await deploy(\"{}\", [{}]))", contract, self.render_list(arguments))
+            }
+            Expr::New { contract, arguments } => format!(
+                "undefined /* new {}({}): no bytecode available */",
+                contract,
+                self.render_list(arguments).replace("*/", "* /")
+            ),
+            Expr::Unary { op, operand } => format!("{}{}", op, self.render_expr(operand)),
+            Expr::Binary { op, left, right } => {
+                let op = match op.as_str() {
+                    "==" => "===",
+                    "!=" => "!==",
+                    other => other,
+                };
+                format!("({} {} {})", self.render_expr(left), op, self.render_expr(right))
+            }
+            Expr::Ternary { condition, if_true, if_false } => format!(
+                "({} ? {} : {})",
+                self.render_expr(condition),
+                self.render_expr(if_true),
+                self.render_expr(if_false)
+            ),
+            Expr::Array { elements } | Expr::Tuple { elements } => format!("[{}]", self.render_list(elements)),
+            Expr::Struct { name, fields, values } => self.render_struct(name, fields, values),
+            Expr::Unsupported { source } => format!("undefined /* {} */", source.replace("*/", "* /")),
         }
     }
-    log_debug(&format!("Failed to parse variable: {}", input));
 
-    "unknownVariable2222".to_string()
-}
-*/
-fn parse_variable_string(input: &str) -> String {
-    let content = input.trim_start_matches("Variable(").trim_end_matches(')');
-    if let Some(identifier) = content.strip_prefix("Identifier ") {
-        let parts: Vec<&str> = split_top_level(identifier.trim_matches('{').trim_matches('}'), ", ");
-        for part in parts {
-            if part.starts_with("name: ") {
-                return part.trim_start_matches("name: ").trim_matches('"').to_string();
+    /// Statement calls are rendered like calls inside expressions, so both agree
+    /// on read vs. write.
+    fn render_statement(&self, step: &TestStep) -> Option<String> {
+        match step {
+            TestStep::VariableDeclaration { name, type_, value, mutable, .. } => Some(format!(
+                "{} {} = {}",
+                if *mutable { "let" } else { "const" },
+                name,
+                value.as_ref().map_or_else(|| zero_value(type_), |value| self.render_expr(value))
+            )),
+            TestStep::TupleDeclaration { variables, value, mutable, .. } => Some(format!(
+                "{} [{}] = {}",
+                if *mutable { "let" } else { "const" },
+                variables.iter().map(|variable| variable.as_ref().map_or("", |variable| variable.name.as_str())).collect::<Vec<_>>().join(", "),
+                self.render_expr(value)
+            )),
+            TestStep::TupleAssignment { targets, value, .. } => Some(format!(
+                "[{}] = {}",
+                targets.iter().map(|target| target.as_ref().map_or_else(String::new, |target| self.render_expr(target))).collect::<Vec<_>>().join(", "),
+                self.render_expr(value)
+            )),
+            TestStep::Assignment { target, op, value, .. } => {
+                Some(format!("{} {} {}", self.render_expr(target), op, self.render_expr(value)))
             }
+            TestStep::Delete { target, type_, .. } => {
+                let type_ = type_.clone().or_else(|| self.resolver.infer_type(target)).unwrap_or_default();
+                Some(format!("{} = {}", self.render_expr(target), zero_value(&type_)))
+            }
+            TestStep::ContractCall { call, .. } => Some(self.render_expr(call)),
+            TestStep::FunctionCall { contract, function, arguments, .. } => {
+                let callee = match contract {
+                    Some(contract) => Expr::MemberAccess {
+                        object: Box::new(Expr::Variable { name: contract.clone() }),
+                        member: function.clone(),
+                    },
+                    None => Expr::Variable { name: function.clone() },
+                };
+                Some(self.render_expr(&Expr::Call { callee: Box::new(callee), arguments: arguments.clone(), value: None }))
+            }
+            _ => None,
         }
     }
-    "unknownVariable".to_string()
-}
 
-
-/*
-    fn parse_arguments_string(input: &str) -> String {
-        log_debug(&format!("---------------START-----------------------------"));
-        log_debug(&format!("parse_arguments_string input: {}", input));
-
-        let args_str = input.trim_start_matches('[').trim_end_matches(']');
-        let result = split_top_level(args_str, ", ")
-            .into_iter()
-            .map(parse_ast_string)
-            .collect::<Vec<String>>()
-            .join(", ");
-        log_debug(&format!("parse_arguments_string result: {}", result));
-        log_debug(&format!("-------------END-----------------------------------"));
-
-        result
-    }
-*/
-fn parse_arguments_string(input: &str) -> String {
-    let args_str = input.trim_start_matches('[').trim_end_matches(']');
-    let parsed_args = split_top_level(args_str, ", ")
-        .into_iter()
-        .map(parse_ast_string)
-        .collect::<Vec<String>>();
-    
-    if parsed_args.is_empty() {
-        log_debug(&format!("No arguments found in: {}", input));
-    }
-    
-    parsed_args.join(", ")
-}
-
-    /*
-    fn parse_number_literal_string(input: &str) -> String {
-        log_debug(&format!("parse_number_literal_string input: {}", input));
-
-        let parts: Vec<&str> = input.split(", ").collect();
-        let result = if parts.len() >= 2 {
-            let value = parts[1].trim_matches('"');
-            format!("BigInt({}e18)", value)
-        } else {
-            "BigInt(0)".to_string()
+    /// Struct values become objects keyed by the ABI component names, which is
+    /// how viem encodes tuples, cast to the struct's declared TypeScript type.
+    /// Structs whose components are unnamed, or that no ABI mentions, fall
+    /// back to a positional array.
+    fn render_struct(&self, name: &str, fields: &[String], values: &[Expr]) -> String {
+        let param = self.struct_param(name);
+        let names: Vec<&str> = match param {
+            _ if !fields.is_empty() => fields.iter().map(String::as_str).collect(),
+            Some(param) if param.components.iter().all(|component| !component.name.is_empty()) => {
+                param.components.iter().map(|component| component.name.as_str()).collect()
+            }
+            _ => Vec::new(),
         };
-        log_debug(&format!("parse_number_literal_string output: {}", result));
-
-        result
-    }
-
-
-fn parse_number_literal_string(input: &str) -> String {
-    let parts: Vec<&str> = input.split(", ").collect();
-    if parts.len() >= 3 {
-        let value = parts[1].trim_matches('"');
-        let decimals = parts[2].trim_matches('"').parse().unwrap_or(0);
-        if decimals > 0 {
-            format!("BigInt(\"{}{}\")", value, "0".repeat(decimals))
+        let value = if names.len() == values.len() {
+            let entries: Vec<String> = names.iter()
+                .zip(values)
+                .map(|(name, value)| format!("{}: {}", name, self.render_expr(value)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
         } else {
-            format!("BigInt({})", value)
+            format!("[{}]", self.render_list(values))
+        };
+        match param.and_then(FunctionParameter::struct_name) {
+            Some(struct_name) => format!("({} as {})", value, struct_name),
+            None => value,
         }
-    } else {
-        log_debug(&format!("Failed to parse number literal: {}", input));
-        "BigInt(0)".to_string()
-    }
-}
-*/
-fn parse_number_literal_string(input: &str) -> String {
-    let content = input.trim_start_matches("NumberLiteral(").trim_end_matches(')');
-    let parts: Vec<&str> = split_top_level(content, ", ");
-    if parts.len() >= 2 {
-        parts[1].trim_matches('"').to_string()
-    } else {
-        "0".to_string()
     }
-}
-
 
-    /*
-    fn split_top_level<'a>(input: &'a str, delimiter: &str) -> Vec<&'a str> {
-        log_debug(&format!("split_top_level input: {}, delimiter: {}", input, delimiter));
-
-        let mut result = Vec::new();
-        let mut current_start = 0;
-        let mut depth = 0;
-    
-        for (i, c) in input.char_indices() {
-            match c {
-                '(' | '[' => depth += 1,
-                ')' | ']' => depth -= 1,
-                _ if c == delimiter.chars().next().unwrap() && depth == 0 => {
-                    if current_start < i {
-                        result.push(&input[current_start..i]);
-                    }
-                    current_start = i + 1;
-                },
-                _ => {}
+    /// The ABI parameter of struct type `name` (bare or `Contract.Struct`) in
+    /// the functions of the tested or the other deployed contracts.
+    fn struct_param(&self, name: &str) -> Option<&FunctionParameter> {
+        fn find<'p>(param: &'p FunctionParameter, name: &str) -> Option<&'p FunctionParameter> {
+            if param.struct_name() == Some(name) {
+                return Some(param);
             }
+            param.components.iter().find_map(|component| find(component, name))
         }
-    
-        if current_start < input.len() {
-            result.push(&input[current_start..]);
-        }
-        log_debug(&format!("split_top_level input: {:?}", result));
 
-        result
+        let name = name.rsplit('.').next().unwrap_or(name);
+        std::iter::once(self.abi).chain(self.dependencies)
+            .flat_map(|abi| abi.functions.iter().flat_map(|function| function.inputs.iter().chain(&function.outputs)).chain(abi.constructor.iter().flat_map(|constructor| &constructor.inputs)))
+            .find_map(|param| find(param, name))
     }
 
-*/
-/*
-FunctionCall(File(0, 459, 486), 
-    MemberAccess(File(0, 459, 473), 
-    Variable(
-        Identifier { loc: File(0, 459, 464), name: \\\"token\\\" }), 
-        Identifier { loc: File(0, 465, 473), name: \\\"transfer\\\" }), 
-        [
-        Variable(
-            Identifier { loc: File(0, 474, 477), name: \\\"bob\\\" }), 
-            NumberLiteral(File(0, 479, 485), \\\"100\\\", \\\"18\\\", None)])
-
-
-
-
-
-
-fn split_top_level<'a>(input: &'a str, delimiter: &str) -> Vec<&'a str> {
-    let mut result = Vec::new();
-        let mut current_start = 0;
-        let mut depth = 0;
-        log_debug(&format!("delimiter here {}", delimiter));
-
-        for (i, c) in input.char_indices() {
-            log_debug(&format!("here is char {}", c));
-
-            match c {
-                '(' | '[' => {depth += 1;log_debug(&format!("we hit some depth up"));},
-                ')' | ']' => {depth -= 1;log_debug(&format!("we hit some depth down"));},
-                _ if c == delimiter.chars().next().unwrap() && depth == 0 => {
-
-                    if current_start < i {
-                        result.push(&input[current_start..i]);
-                    }
-                    current_start = i + delimiter.len();
-                },
-                _ => {}
+    /// The ABI parameters of the structs the test constructs, whose types the
+    /// casts in [`JsRenderer::render_struct`] refer to.
+    fn constructed_structs(&self, test_contract: &TestContract) -> Vec<&FunctionParameter> {
+        let mut structs = Vec::new();
+        let mut collect = |expr: &Expr| expr.visit(&mut |expr| {
+            if let Expr::Struct { name, .. } = expr {
+                structs.extend(self.struct_param(name));
             }
+        });
+        test_contract.state_variables.iter().flat_map(|var| &var.value).for_each(&mut collect);
+        for function in test_contract.functions() {
+            function.all_steps().into_iter().flat_map(|step| step.expressions()).for_each(&mut collect);
         }
-    
-        if current_start < input.len() {
-            result.push(&input[current_start..]);
-        }
-
-        log_debug(&format!("split_top_level result: {:#?}", result));
-
+        structs
+    }
+
+    fn render_list(&self, exprs: &[Expr]) -> String {
+        exprs.iter().map(|expr| self.render_expr(expr)).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Calls to `view`/`pure` functions go through the component's `read`, and
+    /// everything else through `send`, which simulates first so the call still
+    /// yields its return value. The receiver variable holds the contract address.
+    /// Overloaded functions pass the resolved signature along so viem encodes
+    /// exactly that overload, and so do functions of the other deployed
+    /// contracts, which are not in the component's ABI.
+    /// Ether sent with `{value: ...}` follows the signature, or `undefined`
+    /// in its place.
+    fn render_contract_call(&self, receiver: &Expr, function: &str, arguments: &[Expr], value: Option<&Expr>) -> String {
+        let address = self.render_expr(receiver);
+        let value = value.map(|value| self.render_expr(value));
+        let resolved = match self.resolver.resolve(receiver, function, arguments) {
+            Resolution::Unique(resolved) => resolved,
+            _ => {
+                let options = value.map(|value| format!(", undefined, {}", value)).unwrap_or_default();
+                return format!("(await send({}, \"{}\", [{}]{}))", address, function, self.render_list(arguments), options);
+            }
+        };
+        let args = arguments.iter()
+            .zip(&resolved.inputs)
+            .map(|(arg, param)| self.render_argument(arg, &param.type_))
+            .collect::<Vec<_>>()
+            .join(", ");
 
-        result
+        let runner = if resolved.is_read_only() { "read" } else { "send" };
+        let signature = (self.resolver.is_overloaded(receiver, function) || self.resolver.is_dependency(receiver))
+            .then(|| format!("\"{}\"", resolved.human_readable()));
+        let options = match (signature, value) {
+            (signature, Some(value)) => format!(", {}, {}", signature.as_deref().unwrap_or("undefined"), value),
+            (Some(signature), None) => format!(", {}", signature),
+            (None, None) => String::new(),
+        };
+        format!("(await {}({}, \"{}\", [{}]{}))", runner, address, function, args, options)
+    }
+
+    /// `payable(to).transfer(amount)`, `to.send(amount)` and
+    /// `to.call{value: amount}(data)`, which go to the address itself rather
+    /// than through an ABI. `send` yields only the success flag of `call`.
+    fn render_address_call(&self, receiver: &Expr, function: &str, arguments: &[Expr], value: Option<&Expr>) -> String {
+        let address = self.render_expr(receiver);
+        let first = arguments.first();
+        match function {
+            "transfer" => format!("(await transferValue({}, {}))", address, first.map_or_else(|| "0n".to_string(), |amount| self.render_expr(amount))),
+            "send" => format!("(await callAddress({}, {}, \"0x\"))[0]", address, first.map_or_else(|| "0n".to_string(), |amount| self.render_expr(amount))),
+            _ => format!(
+                "(await callAddress({}, {}, {}))",
+                address,
+                value.map_or_else(|| "0n".to_string(), |value| self.render_expr(value)),
+                first.map_or_else(|| "\"0x\"".to_string(), |data| self.render_argument(data, "bytes"))
+            ),
+        }
     }
 
-
-
-
-
-fn split_top_level<'a>(input: &'a str, delimiter: &str) -> Vec<&'a str> {
-
-        let mut result = Vec::new();
-        let mut current_start = 0;
-        let mut depth = 0;
-        let mut in_quotes = false;
-        log_debug(&format!("split_top_level input: {:#?}", input));
-        log_debug(&format!("split_top_level delimiter: {}", delimiter));
-
-
-        for (i, c) in input.char_indices() {
-            match c {
-                '(' => if !in_quotes { depth += 1 },
-                ')' => if !in_quotes { 
-                    log_debug(&format!("split_top_level push: {:#?}", &input[current_start..=i]));
-
-                    depth -= 1;
-                    if depth == 0 && input[current_start..=i].starts_with("FunctionCall(") {
-                        result.push(&input[current_start..=i]);
-                        current_start = i + 1;
+    /// Module-level declarations for the imported constants: `const MAX = ...;`
+    /// for file-level ones, and an object per contract or enum holding the
+    /// rest, so `Constants.MAX` and `Status.Active` read the same as in Solidity.
+    fn render_constants(&self, constants: &[Constant]) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut scopes: Vec<&str> = Vec::new();
+        for constant in constants {
+            let value = self.render_expr(&constant.value);
+            match constant.scope.as_deref() {
+                None => lines.push(format!("const {} = {};", constant.name, value)),
+                Some(scope) => {
+                    if !scopes.contains(&scope) {
+                        scopes.push(scope);
+                        lines.push(format!("const {} = {{}};", scope));
                     }
-                    log_debug(&format!("split_top_level current_result: {:#?}", result));
-
-                },
-                '"' => in_quotes = !in_quotes,
-                '\\' => { /* Skip the next character if it's an escape */ },
-                _ if c == delimiter.chars().next().unwrap() && depth == 0 && !in_quotes => {
-                    if current_start < i {
-                        result.push(&input[current_start..i]);
-                    }
-                    current_start = i + delimiter.len();
-                },
-                _ => {}
+                    lines.push(format!("{}.{} = {};", scope, constant.name, value));
+                }
             }
         }
-    
-        if current_start < input.len() {
-            result.push(&input[current_start..]);
-        }
-    
-        // If no splits were made, return the entire input as a single item
-        if result.is_empty() {
-            result.push(input);
-        }
-        
-        log_debug(&format!("split_top_level result: {:#?}", result));
-
-        result
+        lines
     }
 
-*/
-
-fn split_top_level<'a>(input: &'a str, delimiter: &str) -> Vec<&'a str> {
-    let mut result = Vec::new();
-    let mut current_start = 0;
-    let mut depth = 0;
-    let mut in_quotes = false;
-
-    for (i, c) in input.char_indices() {
-        match c {
-            '(' => if !in_quotes { depth += 1 },
-            ')' => if !in_quotes { depth -= 1 },
-            '"' => in_quotes = !in_quotes,
-            '\\' => { /* Skip the next character if it's an escape */ },
-            _ if c == delimiter.chars().next().unwrap() && depth == 0 && !in_quotes => {
-                result.push(input[current_start..i].trim());
-                current_start = i + delimiter.len();
-            },
-            _ => {}
-        }
+    fn can_deploy(&self, contract: &str) -> bool {
+        std::iter::once(self.abi).chain(self.dependencies)
+            .any(|abi| abi.bytecode.is_some() && abi.contract_name.as_deref() == Some(contract))
     }
 
-    if current_start < input.len() {
-        result.push(input[current_start..].trim());
+    /// The JS value a state variable starts with: its initializer if it has one,
+    /// otherwise Solidity's zero value. A variable typed as the contract under
+    /// test defaults to `CONTRACT_ADDRESS`, for tests that never deploy it.
+    fn render_state_initializer(&self, type_: &str, value: Option<&Expr>) -> String {
+        if let Some(value) = value {
+            return self.render_expr(value);
+        }
+        match type_ {
+            ty if self.abi.contract_name.as_deref().map_or(ty.chars().next().is_some_and(char::is_uppercase), |name| name == ty) => {
+                "import.meta.env.CONTRACT_ADDRESS".to_string()
+            }
+            ty => zero_value(ty),
+        }
     }
 
-    result
-}
-
-/*
-    fn split_top_level<'a>(input: &'a str, delimiter: &str) -> Vec<&'a str> {
-        let mut result = Vec::new();
-        let mut current_start = 0;
-        let mut depth = 0;
-        let mut in_quotes = false;
-        log_debug(&format!("split_top_level input: {:#?}", input));
-        log_debug(&format!("split_top_level delimiter: {}", delimiter));
-    
-        for (i, c) in input.char_indices() {
-            match c {
-                '(' => {
-                    if !in_quotes { 
-                        depth += 1;
-                        if depth == 1 && input[current_start..i].trim() == "FunctionCall" {
-                            current_start = i + 1;
-                        }
-                    }
-                },
-                ')' => if !in_quotes { depth -= 1 },
-                '"' => in_quotes = !in_quotes,
-                '\\' => { /* Skip the next character if it's an escape */ },
-                _ if c == delimiter.chars().next().unwrap() && depth == 1 && !in_quotes => {
-                    result.push(input[current_start..i].trim());
-                    current_start = i + delimiter.len();
-                    log_debug(&format!("split_top_level push: {:#?}", result.last().unwrap()));
-                },
-                _ => {}
+    /// The viem equivalent of a forge-std or Solidity builtin call, if there is one.
+    fn render_builtin(&self, name: &str, arguments: &[Expr]) -> Option<String> {
+        let rendered = |index: usize| arguments.get(index).map(|arg| self.render_argument(arg, "bytes"));
+        match name {
+            "makeAddr" => Some(format!("makeAddr({})", self.render_expr(arguments.first()?))),
+            "vm.addr" => Some(format!("addressOf({})", self.render_expr(arguments.first()?))),
+            "keccak256" => Some(format!("keccak256({})", rendered(0)?)),
+            "abi.encode" if arguments.is_empty() => Some("\"0x\"".to_string()),
+            "abi.encode" => Some(format!("encodeAbiParameters(parseAbiParameters({}), [{}])", json!(self.abi_types(arguments)?.join(", ")), self.render_list(arguments))),
+            "abi.encodePacked" => Some(format!("encodePacked({}, [{}])", json!(self.abi_types(arguments)?), self.render_list(arguments))),
+            "abi.encodeWithSelector" => {
+                let (selector, arguments) = arguments.split_first()?;
+                Some(format!("concat([{}, {}])", self.render_expr(selector), self.render_builtin("abi.encode", arguments)?))
             }
+            "abi.encodeWithSignature" => {
+                let (signature, arguments) = arguments.split_first()?;
+                Some(format!("concat([toFunctionSelector({}), {}])", self.render_expr(signature), self.render_builtin("abi.encode", arguments)?))
+            }
+            "bytes.concat" => Some(format!("concat([{}])", (0..arguments.len()).filter_map(rendered).collect::<Vec<_>>().join(", "))),
+            // `console.log`, `console2.logUint` and the like.
+            name if name.starts_with("console.log") || name.starts_with("console2.log") => {
+                Some(format!("console.log({})", self.render_list(arguments)))
+            }
+            "string.concat" => Some(format!("[{}].join(\"\")", self.render_list(arguments))),
+            _ => None,
         }
-    
-        if current_start < input.len() {
-            result.push(input[current_start..].trim());
-            log_debug(&format!("split_top_level push: {:#?}", result.last().unwrap()));
-        }
-    
-        log_debug(&format!("split_top_level result: {:#?}", result));
-    
-        result
     }
 
-*/
-
-fn parse_ast(ast: &str) -> String {
-    serde_json::from_str(ast)
-        .map(|v| parse_value(&v))
-        .unwrap_or_else(|_| "null".to_string())
-}
-
-
-fn parse_value(value: &Value) -> String {
-    match value {
-        Value::String(s) => parse_ast(s),
-        Value::Object(map) => {
-            if let Some(typ) = map.get("type") {
-                match typ.as_str() {
-                    Some("FunctionCall") => parse_function_call(map),
-                    Some("MemberAccess") => parse_member_access(map),
-                    Some("Variable") => parse_variable(map),
-                    Some("Identifier") => parse_identifier(map),
-                    Some("NumberLiteral") => parse_number_literal(map),
-                    Some("HexNumberLiteral") => parse_hex_number_literal(map),
-                    _ => value.to_string(),
-                }
-            } else {
-                value.to_string()
-            }
-        },
-        Value::Array(arr) => {
-            let parsed: Vec<String> = arr.iter().map(parse_value).collect();
-            format!("[{}]", parsed.join(", "))
-        },
-        _ => value.to_string(),
+    /// The ABI types `abi.encode` and `abi.encodePacked` encode `arguments`
+    /// as, when all of them can be inferred. Literals take the widest type.
+    fn abi_types(&self, arguments: &[Expr]) -> Option<Vec<String>> {
+        arguments.iter()
+            .map(|arg| match self.resolver.infer_type(arg)?.as_str() {
+                "int_const" => Some("uint256".to_string()),
+                "string_const" => Some("string".to_string()),
+                "address payable" => Some("address".to_string()),
+                "tuple" => None,
+                type_ if type_.starts_with(|c: char| c.is_ascii_uppercase()) => Some("address".to_string()),
+                type_ => Some(type_.to_string()),
+            })
+            .collect()
     }
-}
 
-fn parse_argument(arg: &Value) -> String {
-    println!("argument: {:#?}",arg);
-    match arg {
-        Value::Object(map) => {
-            if let Some(typ) = map.get("type") {
-                match typ.as_str() {
-                    Some("FunctionCall") => parse_function_call(map),
-                    Some("Variable") => parse_variable(map),
-                    Some("NumberLiteral") => parse_number_literal(map),
-                    Some(t) => {
-                        log_debug(&format!("Unknown type in parse_argument: {}", t));
-                        arg.to_string()
-                    },
-                    None => {
-                        log_debug("Type is not a string in parse_argument");
-                        arg.to_string()
-                    }
-                }
-            } else {
-                log_debug("No type field found in parse_argument");
-                arg.to_string()
+    /// Solidity lets string literals stand in for `bytes`; viem wants hex there.
+    fn render_argument(&self, arg: &Expr, param_type: &str) -> String {
+        match arg {
+            Expr::String { value } if param_type.starts_with("bytes") && !param_type.contains('[') => {
+                format!("\"0x{}\"", value.bytes().map(|byte| format!("{:02x}", byte)).collect::<String>())
             }
-        },
-        Value::Array(arr) => {
-            let parsed: Vec<String> = arr.iter().map(|v| parse_argument(v)).collect();
-            format!("[{}]", parsed.join(", "))
-        },
-        Value::String(s) => format!("\"{}\"", s),
-        Value::Number(n) => n.to_string(),
-        Value::Bool(b) => b.to_string(),
-        Value::Null => "null".to_string(),
+            _ => self.render_expr(arg),
+        }
     }
-}
 
-
-
-fn parse_hex_number_literal(map: &serde_json::Map<String, Value>) -> String {
-    map.get("value")
-       .and_then(|v| v.as_str())
-       .map(|s| format!("BigInt(\"{}\")", s))
-       .unwrap_or_else(|| "BigInt(0)".to_string())
-}
-
-fn extract_function_name(func_call: &str) -> String {
-    // Look for the last occurrence of 'Identifier'
-    log_debug(&format!("func_call input: {:?}", func_call));
-
-    if let Some(last_identifier) = func_call.rfind("Identifier") {
-        if let Some(start) = func_call[last_identifier..].find("name: \"") {
-            let start = last_identifier + start + 7;
-            if let Some(end) = func_call[start..].find('"') {
-                return func_call[start..start+end].to_string();
+    fn render_cast(&self, ty: &str, value: &Expr) -> String {
+        match (ty, value) {
+            // The wallet account stands in for the test contract, as the default sender.
+            ("address" | "address payable", Expr::Variable { name }) if name == "this" => {
+                "clients.current.walletClient.account.address".to_string()
+            }
+            ("address" | "address payable", Expr::Number { value }) => format!("\"0x{:0>40}\"", number_to_hex(value)),
+            // `bytes32(0)` and friends: left-padded like the number they convert.
+            (ty, Expr::Number { value }) if ty.strip_prefix("bytes").and_then(|size| size.parse::<usize>().ok()).is_some() => {
+                let size: usize = ty[5..].parse().unwrap_or(32);
+                format!("\"0x{:0>width$}\"", number_to_hex(value), width = size * 2)
             }
+            (ty, value) if ty.starts_with("uint") || ty.starts_with("int") => format!("BigInt({})", self.render_expr(value)),
+            (_, value) => self.render_expr(value),
         }
     }
-    "unknownFunction22".to_string()
 }
 
-
-
-fn extract_function_args(func_call: &str) -> Vec<String> {
-    let mut args = Vec::new();
-    let mut current_arg = String::new();
-    let mut depth = 0;
-    let mut in_brackets = false;
-
-    for c in func_call.chars() {
-        match c {
-            '[' => {
-                in_brackets = true;
-                depth += 1;
-                current_arg.push(c);
-            },
-            ']' => {
-                depth -= 1;
-                current_arg.push(c);
-                if depth == 0 {
-                    in_brackets = false;
-                    if !current_arg.is_empty() {
-                        args.push(current_arg.trim().to_string());
-                        current_arg.clear();
-                    }
-                }
-            },
-            ',' if in_brackets && depth == 1 => {
-                if !current_arg.is_empty() {
-                    args.push(current_arg.trim().to_string());
-                    current_arg.clear();
-                }
-            },
-            _ if in_brackets => current_arg.push(c),
-            _ => {}
+/// The hex digits of an integer literal, for `address(...)` and `bytesN(...)`.
+/// Decimal literals go up to 256 bits, so they are divided down digit by
+/// digit rather than parsed into a machine integer.
+fn number_to_hex(value: &str) -> String {
+    if let Some(hex) = value.strip_prefix("0x") {
+        return hex.to_lowercase();
+    }
+    let mut digits: Vec<u32> = value.bytes().map(|digit| u32::from(digit.wrapping_sub(b'0'))).collect();
+    if digits.iter().any(|&digit| digit > 9) {
+        return String::new();
+    }
+    let mut hex = Vec::new();
+    while digits.iter().any(|&digit| digit != 0) {
+        let mut remainder = 0;
+        for digit in &mut digits {
+            let current = remainder * 10 + *digit;
+            *digit = current / 16;
+            remainder = current % 16;
         }
+        hex.extend(char::from_digit(remainder, 16));
     }
-
-    args
-}
-
-///
-fn parse_assertion_function_helper(h: &handlebars::Helper, _: &handlebars::Handlebars, _: &handlebars::Context, _: &mut handlebars::RenderContext, out: &mut dyn handlebars::Output) -> handlebars::HelperResult {
-    let param = h.param(0).and_then(|v| v.value().as_str()).unwrap_or("");
-    let function = extract_function_name(param);
-
-    log_debug(&format!("parse_assertion_function_helper param {:#?}:",param));
-    log_debug(&format!("parse_assertion_function_helper function {:#?}:",function));
-
-    out.write(&function)?;
-    Ok(())
-}
-
-fn parse_assertion_args_helper(h: &handlebars::Helper, _: &handlebars::Handlebars, _: &handlebars::Context, _: &mut handlebars::RenderContext, out: &mut dyn handlebars::Output) -> handlebars::HelperResult {
-    let param = h.param(0).and_then(|v| v.value().as_str()).unwrap_or("");
-    let args = extract_function_args(param);
-
-    log_debug(&format!("parse_assertion_args_helper param {:#?}:",param));
-    log_debug(&format!("parse_assertion_args_helper function {:#?}:",args));
-    out.write(&args.join(", "))?;
-    Ok(())
-}
-
-fn generate_assertion_var_helper(h: &handlebars::Helper, _: &handlebars::Handlebars, _: &handlebars::Context, _: &mut handlebars::RenderContext, out: &mut dyn handlebars::Output) -> handlebars::HelperResult {
-    let param = h.param(0).and_then(|v| v.value().as_str()).unwrap_or("");
-    let function = extract_function_name(param);
-    let var_name = format!("{}Result", function);
-    out.write(&var_name)?;
-    Ok(())
+    if hex.is_empty() {
+        return "0".to_string();
+    }
+    hex.iter().rev().collect()
 }
 
-fn json_helper(h: &handlebars::Helper, _: &handlebars::Handlebars, _: &handlebars::Context, _: &mut handlebars::RenderContext, out: &mut dyn handlebars::Output) -> handlebars::HelperResult {
-
-    let param = h.param(0).ok_or_else(|| RenderError::new("Param 0 is required for json helper"))?;
-    let json_str = serde_json::to_string(param.value())
-        .map_err(|e| RenderError::new(format!("Failed to serialize to JSON: {}", e)))?;
-    out.write(&json_str)?;
-    Ok(())
+/// Solidity's default value for `ty`, as used for uninitialized variables and `delete`.
+fn zero_value(ty: &str) -> String {
+    match ty {
+        "bool" => "false".to_string(),
+        "string" => "\"\"".to_string(),
+        "bytes" => "\"0x\"".to_string(),
+        "address" | "address payable" => format!("\"0x{}\"", "0".repeat(40)),
+        ty if ty.starts_with("uint") || ty.starts_with("int") => "0n".to_string(),
+        ty if ty.starts_with("bytes") => format!("\"0x{}\"", "00".repeat(ty[5..].parse().unwrap_or(32))),
+        _ => "undefined".to_string(),
+    }
 }
 
+/// `type(uint8).max` and friends, evaluated as bigint expressions.
+fn type_bound(object: &Expr, member: &str) -> Option<String> {
+    let Expr::Call { callee, arguments, .. } = object else { return None };
+    if !matches!(callee.as_ref(), Expr::Variable { name } if name == "type") {
+        return None;
+    }
+    let Some(Expr::Type { name }) = arguments.first() else { return None };
 
-fn parse_assertion(arg: &str) -> String {
-    let v: Value = match serde_json::from_str(arg) {
-        Ok(v) => v,
-        Err(e) => {
-            log_debug(&format!("Failed to parse JSON in parse_assertion: {}", e));
-            return "null".to_string();
-        }
+    let (signed, bits) = match name.strip_prefix("uint") {
+        Some(bits) => (false, bits),
+        None => (true, name.strip_prefix("int")?),
     };
-
-    parse_argument(&v)
+    let bits: u32 = if bits.is_empty() { 256 } else { bits.parse().ok()? };
+
+    match (signed, member) {
+        (false, "max") => Some(format!("(2n ** {}n - 1n)", bits)),
+        (false, "min") => Some("0n".to_string()),
+        (true, "max") => Some(format!("(2n ** {}n - 1n)", bits - 1)),
+        (true, "min") => Some(format!("(-(2n ** {}n))", bits - 1)),
+        _ => None,
+    }
+}
+
+/// Finds the ABI entries for every contract function the tests call, so their
+/// signatures come from the ABI rather than being guessed from the call sites.
+fn extract_contract_functions<'a>(test_contract: &TestContract, abi: &'a [ContractFunction]) -> Vec<&'a ContractFunction> {
+    let mut called = vec![];
+    for function in test_contract.functions() {
+        extract_functions_from_steps(&function.all_steps(), &mut called);
+    }
+
+    // The invariant explorer calls any state-changing function of the target.
+    let explored = !test_contract.invariants.is_empty();
+    abi.iter()
+        .filter(|function| called.contains(&function.name) || (explored && !function.is_read_only()))
+        .collect()
+}
+
+/// `vm.addr`, `abi.encode`, `keccak256` and the like: calls on a Solidity or
+/// forge-std builtin, named the way Solidity spells them.
+fn builtin_name(callee: &Expr) -> Option<String> {
+    const GLOBAL_FUNCTIONS: &[&str] = &[
+        "makeAddr", "keccak256", "sha256", "ripemd160", "ecrecover", "blockhash", "blobhash", "gasleft", "addmod", "mulmod", "selfdestruct",
+    ];
+    match callee {
+        Expr::Variable { name } if GLOBAL_FUNCTIONS.contains(&name.as_str()) => Some(name.clone()),
+        Expr::MemberAccess { object, member } => match object.as_ref() {
+            Expr::Variable { name } if is_builtin_receiver(name) && name != "super" => Some(format!("{}.{}", name, member)),
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
-
-fn extract_contract_functions(test_contract: &TestContract) -> Vec<String> {
-    let mut functions = vec![];
-    if let Some(setup) = &test_contract.setup {
-        extract_functions_from_steps(&setup.steps, &mut functions);
+/// TypeScript declarations for the structs the called functions take or return
+/// and those the test constructs, nested structs included, keyed by their
+/// `internalType` name.
+fn extract_struct_types(functions: &[&ContractFunction], constructed: &[&FunctionParameter]) -> Vec<Value> {
+    fn collect(param: &FunctionParameter, structs: &mut Vec<Value>) {
+        param.components.iter().for_each(|component| collect(component, structs));
+        if let Some(name) = param.struct_name() {
+            if !structs.iter().any(|existing| existing["name"] == name) {
+                structs.push(json!({ "name": name, "fields": param.typescript_fields() }));
+            }
+        }
     }
-    for test_function in &test_contract.test_functions {
-        extract_functions_from_steps(&test_function.steps, &mut functions);
+
+    let mut structs = Vec::new();
+    for function in functions {
+        function.inputs.iter().chain(&function.outputs).for_each(|param| collect(param, &mut structs));
     }
-    functions.sort();
-    functions.dedup();
-    functions
+    constructed.iter().for_each(|param| collect(param, &mut structs));
+    structs
 }
 
-fn extract_functions_from_steps(steps: &[TestStep], functions: &mut Vec<String>) {
+fn extract_functions_from_steps(steps: &[&TestStep], functions: &mut Vec<String>) {
     for step in steps {
-        if let TestStep::FunctionCall { function, arguments, .. } = step {
-            let args = arguments.iter().map(|_| "address".to_string()).collect::<Vec<_>>().join(", ");
-            functions.push(format!("function {}({}) public", function, args));
+        if let TestStep::FunctionCall { contract: Some(contract), function, .. } = step {
+            if !is_builtin_receiver(contract) {
+                functions.push(function.clone());
+            }
         }
+        step.expressions().into_iter().for_each(|expr| extract_functions_from_expr(expr, functions));
     }
 }
+
+fn extract_functions_from_expr(expr: &Expr, functions: &mut Vec<String>) {
+    expr.visit(&mut |expr| {
+        if let Some((_, function, _)) = expr.contract_call() {
+            functions.push(function.to_string());
+        }
+        if let Expr::MemberAccess { object, member } = expr {
+            if let (Expr::MemberAccess { member: function, .. }, "selector") = (object.as_ref(), member.as_str()) {
+                functions.push(function.clone());
+            }
+        }
+    });
+}
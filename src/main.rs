@@ -1,35 +1,214 @@
-use eyre::Result;
+use eyre::{eyre, Result};
 use clap::Parser;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
 
 mod abi_parser;
 mod foundry_test_parser;
 mod generator;
+mod project;
+mod resolver;
+mod validator;
 
 #[derive(Parser)]
 #[clap(version = "1.0", author = "Alp Guneysel")]
 struct Opts {
+    /// ABI or Forge artifact of the contract under test; in project mode only
+    /// used for test contracts whose target has no artifact in `out`
     #[clap(short, long)]
-    abi: PathBuf,
+    abi: Option<PathBuf>,
     
     #[clap(short, long)]
     test: Option<PathBuf>,
+
+    /// Generate for every test file of the Foundry project at this path
+    #[clap(short, long, conflicts_with = "test")]
+    project: Option<PathBuf>,
     
+    /// Component file, or directory for files with several test contracts and in project mode
     #[clap(short, long)]
     output: PathBuf,
+
+    /// Fail instead of warning when test calls do not match the ABI
+    #[clap(long)]
+    strict: bool,
 }
 
 fn main() -> Result<()> {
     let opts: Opts = Opts::parse();
 
-    let test_contract = if let Some(test_path) = opts.test {
-        foundry_test_parser::parse_foundry_test_file(&test_path)?
-    } else {
+    if let Some(root) = &opts.project {
+        return generate_project(root, &opts);
+    }
+
+    let Some(test_path) = &opts.test else {
         println!("No test file provided. Skipping test parsing.");
         return Ok(());
     };
+    let test_contracts = foundry_test_parser::parse_foundry_test_file(test_path)?;
+    if test_contracts.is_empty() {
+        return Err(eyre!("No test contract found"));
+    }
+
+    let abi_path = opts.abi.as_ref().ok_or_else(|| eyre!("--abi is required with --test"))?;
+    let abi = abi_parser::parse_abi(abi_path)?;
+    let project = project::Project::find(test_path)?;
+
+    for test_contract in &test_contracts {
+        let output = output_path(&opts.output, &test_contract.name, test_contracts.len());
+        let dependencies = deployed_artifacts(test_contract, &project, abi.contract_name.as_deref())?;
+        write_component(test_contract, &abi, &dependencies, &output, opts.strict)?;
+    }
+
+    println!("\nReact component generation somewhat implemented");
+    Ok(())
+}
+
+/// Project mode: a component for every test contract under the project's
+/// test directory, laid out like the test files, plus an `index.ts` that
+/// exports them all with a catalog of their tests. Files that fail to parse
+/// are reported and skipped.
+fn generate_project(root: &Path, opts: &Opts) -> Result<()> {
+    let project = project::Project::load(root)?;
+    let fallback_abi = opts.abi.as_deref().map(abi_parser::parse_abi).transpose()?;
+    let test_dir = project.root.join(&project.test);
+
+    let mut parsed = Vec::new();
+    for file in project.test_files()? {
+        match foundry_test_parser::parse_project_test_file(&file, &project) {
+            Ok(test_contracts) => parsed.extend(test_contracts.into_iter().map(|test_contract| (file.clone(), test_contract))),
+            Err(error) => eprintln!("warning: skipping {}: {}", file.display(), error),
+        }
+    }
+
+    let modules = component_modules(&parsed, &test_dir);
+    let mut generated = Vec::new();
+    for ((file, test_contract), module) in parsed.into_iter().zip(modules) {
+        let target = contract_under_test(&test_contract, &project);
+        let abi = match &target {
+            Some((_, artifact)) => abi_parser::parse_abi(artifact)?,
+            None => fallback_abi.clone().unwrap_or_default(),
+        };
+        let output = opts.output.join(&module).with_extension("tsx");
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let dependencies = deployed_artifacts(&test_contract, &project, target.as_ref().map(|(name, _)| name.as_str()))?;
+        write_component(&test_contract, &abi, &dependencies, &output, opts.strict)?;
+
+        let file = file.strip_prefix(&project.root).unwrap_or(&file).display().to_string();
+        let module = module.to_string_lossy().replace('\\', "/");
+        generated.push((test_contract, file, module, target.map(|(name, _)| name)));
+    }
+
+    let entries: Vec<generator::IndexEntry> = generated.iter()
+        .map(|(test_contract, file, module, target)| generator::IndexEntry {
+            test_contract,
+            file: file.clone(),
+            module: module.clone(),
+            target: target.clone(),
+        })
+        .collect();
+    let index = opts.output.join("index.ts");
+    fs::write(&index, generator::generate_index(&entries)?)?;
+    println!("\nWrote {} with {} test contract(s)", index.display(), entries.len());
+    Ok(())
+}
+
+/// Where each component goes in the output directory, laid out like the test
+/// directory: `<Contract>Component`, or `<File><Contract>Component` when test
+/// files in the same directory declare the same contract, so none of them
+/// overwrites another.
+fn component_modules(parsed: &[(PathBuf, foundry_test_parser::TestContract)], test_dir: &Path) -> Vec<PathBuf> {
+    let module = |file: &Path, contract: &str, qualified: bool| {
+        let directory = file.parent().and_then(|parent| parent.strip_prefix(test_dir).ok()).unwrap_or(Path::new(""));
+        if !qualified {
+            return directory.join(format!("{}Component", contract));
+        }
+        let name = file.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let stem = name.trim_end_matches(".sol").trim_end_matches(".t");
+        directory.join(format!("{}{}Component", stem, contract))
+    };
+    let plain: Vec<PathBuf> = parsed.iter().map(|(file, test_contract)| module(file, &test_contract.name, false)).collect();
+    parsed.iter()
+        .zip(&plain)
+        .map(|((file, test_contract), candidate)| match plain.iter().filter(|other| *other == candidate).count() {
+            1 => candidate.clone(),
+            _ => module(file, &test_contract.name, true),
+        })
+        .collect()
+}
 
+/// The contract a test contract exercises, with its build artifact: the first
+/// contract deployed in `setUp`, or else the first contract-typed state
+/// variable, that the project has an artifact for.
+fn contract_under_test(test_contract: &foundry_test_parser::TestContract, project: &project::Project) -> Option<(String, PathBuf)> {
+    let deployed = test_contract.setup.iter()
+        .flat_map(|setup| setup.all_steps())
+        .filter_map(|step| match step {
+            foundry_test_parser::TestStep::Constructor { contract, .. } => Some(contract.clone()),
+            _ => None,
+        });
+    let declared = test_contract.state_variables.iter().map(|var| var.type_.clone());
+    deployed.chain(declared)
+        .filter(|name| name.chars().next().is_some_and(char::is_uppercase))
+        .find_map(|name| project.artifact(&name).map(|artifact| (name, artifact)))
+}
+
+/// The build artifacts of the contracts the test deploys besides `target`,
+/// for those the project has one for, so the component can deploy them too.
+fn deployed_artifacts(test_contract: &foundry_test_parser::TestContract, project: &project::Project, target: Option<&str>) -> Result<Vec<abi_parser::ContractAbi>> {
+    let mut contracts: Vec<&str> = Vec::new();
+    for step in test_contract.functions().flat_map(|function| function.all_steps()) {
+        if let foundry_test_parser::TestStep::Constructor { contract, .. } = step {
+            if Some(contract.as_str()) != target && !contracts.contains(&contract.as_str()) {
+                contracts.push(contract);
+            }
+        }
+    }
+    contracts.into_iter()
+        .filter_map(|contract| project.artifact(contract))
+        .map(|artifact| abi_parser::parse_abi(&artifact))
+        .collect()
+}
+
+/// Validates, generates and writes the component for one test contract.
+fn write_component(
+    test_contract: &foundry_test_parser::TestContract,
+    abi: &abi_parser::ContractAbi,
+    dependencies: &[abi_parser::ContractAbi],
+    output: &Path,
+    strict: bool,
+) -> Result<()> {
+    print_test_contract(test_contract);
+
+    let diagnostics = validator::validate(test_contract, abi, dependencies);
+    let severity = if strict { "error" } else { "warning" };
+    for diagnostic in &diagnostics {
+        eprintln!("{}: {}", severity, diagnostic);
+    }
+    if strict && !diagnostics.is_empty() {
+        return Err(eyre!("{} call(s) in {} do not match the ABI", diagnostics.len(), test_contract.name));
+    }
+
+    let js_code = generator::generate_js_code(test_contract, abi, dependencies)?;
+    fs::write(output, js_code)?;
+    println!("\nWrote {}", output.display());
+    Ok(())
+}
+
+/// Where a contract's component goes. A file with a single test contract is
+/// written to `--output` itself; with several, or when `--output` is a
+/// directory, each gets `<Contract>Component.tsx` in that directory.
+fn output_path(output: &Path, contract: &str, count: usize) -> PathBuf {
+    if count == 1 && !output.is_dir() {
+        return output.to_path_buf();
+    }
+    let directory = if output.is_dir() { output } else { output.parent().unwrap_or(Path::new(".")) };
+    directory.join(format!("{}Component.tsx", contract))
+}
+
+fn print_test_contract(test_contract: &foundry_test_parser::TestContract) {
     println!("====================================");
     println!("Test Contract: {}", test_contract.name);
     
@@ -47,17 +226,36 @@ fn main() -> Result<()> {
 
     println!("\nTest Functions:");
     for func in &test_contract.test_functions {
-        println!("\nTest: {}", func.name);
+        println!("\nTest: {}{}", func.name, if func.skipped { " (skipped)" } else { "" });
         for step in &func.steps {
             println!("  {:?}", step);
         }
     }
 
+    if !test_contract.invariants.is_empty() {
+        println!("\nInvariants:");
+        for invariant in &test_contract.invariants {
+            println!("\nInvariant: {}", invariant.name);
+            for step in &invariant.steps {
+                println!("  {:?}", step);
+            }
+        }
+    }
 
-    let js_code = generator::generate_js_code(&test_contract)?;
-
-    fs::write(&opts.output, js_code)?;
+    if let Some(after_invariant) = &test_contract.after_invariant {
+        println!("\nAfter invariant:");
+        for step in &after_invariant.steps {
+            println!("  {:?}", step);
+        }
+    }
 
-    println!("\nReact component generation somewhat implemented");
-    Ok(())
-}
\ No newline at end of file
+    if !test_contract.helpers.is_empty() {
+        println!("\nHelpers:");
+        for helper in &test_contract.helpers {
+            println!("\nHelper: {}{}", helper.name, if helper.recursive { " (recursive)" } else { "" });
+            for step in &helper.steps {
+                println!("  {:?}", step);
+            }
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use eyre::{Result, WrapErr};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A Foundry project: its layout from `foundry.toml` and the remappings used
+/// to resolve non-relative imports.
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub root: PathBuf,
+    pub test: String,
+    pub out: String,
+    pub libs: Vec<String>,
+    pub remappings: Vec<Remapping>,
+}
+
+/// `[context:]prefix=target`, with `target` relative to the project root.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remapping {
+    pub context: Option<String>,
+    pub prefix: String,
+    pub target: String,
+}
+
+impl Remapping {
+    fn parse(line: &str) -> Option<Self> {
+        let (left, target) = line.trim().split_once('=')?;
+        let (context, prefix) = match left.split_once(':') {
+            Some((context, prefix)) => (Some(context.to_string()).filter(|context| !context.is_empty()), prefix),
+            None => (None, left),
+        };
+        if prefix.is_empty() {
+            return None;
+        }
+        Some(Remapping { context, prefix: prefix.to_string(), target: target.to_string() })
+    }
+}
+
+impl Project {
+    /// The project containing `path`: the nearest directory upwards with a
+    /// `foundry.toml`. Without one, the directory above the test directory
+    /// is taken as the root with Foundry's default layout.
+    pub fn find(path: &Path) -> Result<Self> {
+        let start = if path.is_dir() { path } else { path.parent().unwrap_or(Path::new(".")) };
+        let start = if start.as_os_str().is_empty() { Path::new(".") } else { start };
+        match start.ancestors().find(|dir| dir.join("foundry.toml").is_file()) {
+            Some(root) => Project::load(root),
+            None => {
+                let root = start.ancestors().find(|dir| dir.ends_with("test")).and_then(Path::parent).unwrap_or(start);
+                Ok(Project::with_defaults(root))
+            }
+        }
+    }
+
+    /// Reads `foundry.toml` and `remappings.txt` in `root`. Like forge, the
+    /// profile named by `FOUNDRY_PROFILE` overrides `[profile.default]`.
+    pub fn load(root: &Path) -> Result<Self> {
+        let mut project = Project::with_defaults(root);
+        let config_path = root.join("foundry.toml");
+        if config_path.is_file() {
+            let content = fs::read_to_string(&config_path)
+                .wrap_err_with(|| format!("Failed to read {}", config_path.display()))?;
+            let name = std::env::var("FOUNDRY_PROFILE").unwrap_or_else(|_| "default".to_string());
+            let profile = read_profile(&content, &name)
+                .wrap_err_with(|| format!("Failed to parse {}", config_path.display()))?;
+            project.test = profile.test.unwrap_or(project.test);
+            project.out = profile.out.unwrap_or(project.out);
+            project.libs = profile.libs.unwrap_or(project.libs);
+            project.remappings.extend(profile.remappings.iter().flatten().filter_map(|line| Remapping::parse(line)));
+        }
+        if let Ok(content) = fs::read_to_string(root.join("remappings.txt")) {
+            project.remappings.extend(content.lines().filter_map(Remapping::parse));
+        }
+        project.remappings.extend(project.detect_lib_remappings());
+        Ok(project)
+    }
+
+    fn with_defaults(root: &Path) -> Self {
+        Project {
+            root: root.to_path_buf(),
+            test: "test".to_string(),
+            out: "out".to_string(),
+            libs: vec!["lib".to_string()],
+            remappings: Vec::new(),
+        }
+    }
+
+    /// Like forge, every dependency in a lib directory gets `name/` remapped to
+    /// its `src/` directory if it has one, or to itself otherwise. Explicit
+    /// remappings for the same prefix win.
+    fn detect_lib_remappings(&self) -> Vec<Remapping> {
+        let mut detected = Vec::new();
+        for lib in &self.libs {
+            let Ok(entries) = fs::read_dir(self.root.join(lib)) else { continue };
+            let mut dependencies: Vec<PathBuf> = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).filter(|path| path.is_dir()).collect();
+            dependencies.sort();
+            for dependency in dependencies {
+                let Some(name) = dependency.file_name().and_then(|name| name.to_str()) else { continue };
+                let prefix = format!("{}/", name);
+                if self.remappings.iter().any(|remapping| remapping.prefix == prefix) {
+                    continue;
+                }
+                let target = if dependency.join("src").is_dir() { format!("{}/{}/src/", lib, name) } else { format!("{}/{}/", lib, name) };
+                detected.push(Remapping { context: None, prefix, target });
+            }
+        }
+        detected
+    }
+
+    /// The file an `import` in `importer` refers to. Relative imports are
+    /// resolved against the importer's directory. Other imports go through the
+    /// remapping with the longest matching prefix, whose context, if any, has
+    /// to contain the importer, and otherwise are taken relative to the root.
+    pub fn resolve_import(&self, importer: &Path, import: &str) -> PathBuf {
+        if import.starts_with("./") || import.starts_with("../") {
+            return importer.parent().unwrap_or(Path::new(".")).join(import);
+        }
+
+        let importer = importer.strip_prefix(&self.root).unwrap_or(importer).to_string_lossy().replace('\\', "/");
+        let remapping = self.remappings.iter()
+            .filter(|remapping| import.starts_with(&remapping.prefix))
+            .filter(|remapping| remapping.context.as_ref().is_none_or(|context| importer.starts_with(context.as_str())))
+            .max_by_key(|remapping| (remapping.context.as_ref().map_or(0, String::len), remapping.prefix.len()));
+        match remapping {
+            Some(remapping) => self.root.join(format!("{}{}", remapping.target, &import[remapping.prefix.len()..])),
+            None => self.root.join(import),
+        }
+    }
+
+    /// Every `*.t.sol` file under the test directory, sorted by path.
+    pub fn test_files(&self) -> Result<Vec<PathBuf>> {
+        fn walk(directory: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+            let entries = fs::read_dir(directory).wrap_err_with(|| format!("Failed to read {}", directory.display()))?;
+            for entry in entries {
+                let path = entry?.path();
+                if path.is_dir() {
+                    walk(&path, files)?;
+                } else if path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.ends_with(".t.sol")) {
+                    files.push(path);
+                }
+            }
+            Ok(())
+        }
+
+        let mut files = Vec::new();
+        walk(&self.root.join(&self.test), &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+
+    /// The build artifact for `contract`, `<out>/<File>.sol/<contract>.json`,
+    /// if the project has been built.
+    pub fn artifact(&self, contract: &str) -> Option<PathBuf> {
+        let mut sources: Vec<PathBuf> = fs::read_dir(self.root.join(&self.out)).ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect();
+        sources.sort();
+        sources.into_iter()
+            .map(|source| source.join(format!("{}.json", contract)))
+            .find(|artifact| artifact.is_file())
+    }
+}
+
+/// The `foundry.toml` settings the generator uses. Everything else in the
+/// file is ignored.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+struct Profile {
+    test: Option<String>,
+    out: Option<String>,
+    libs: Option<Vec<String>>,
+    remappings: Option<Vec<String>>,
+}
+
+impl Profile {
+    fn or(self, base: Profile) -> Profile {
+        Profile {
+            test: self.test.or(base.test),
+            out: self.out.or(base.out),
+            libs: self.libs.or(base.libs),
+            remappings: self.remappings.or(base.remappings),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FoundryToml {
+    #[serde(default)]
+    profile: HashMap<String, Profile>,
+}
+
+/// The settings of profile `name` in `foundry.toml`, falling back to
+/// `[profile.default]` for the keys it does not set.
+fn read_profile(content: &str, name: &str) -> Result<Profile> {
+    let mut config: FoundryToml = toml::from_str(content)?;
+    let default = config.profile.remove("default").unwrap_or_default();
+    Ok(match config.profile.remove(name) {
+        Some(profile) => profile.or(default),
+        None => default,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(remappings: &[&str]) -> Project {
+        let mut project = Project::with_defaults(Path::new("/root"));
+        project.remappings = remappings.iter().filter_map(|line| Remapping::parse(line)).collect();
+        project
+    }
+
+    #[test]
+    fn remapping_parse_reads_context_prefix_and_target() {
+        assert_eq!(
+            Remapping::parse("forge-std/=lib/forge-std/src/"),
+            Some(Remapping { context: None, prefix: "forge-std/".to_string(), target: "lib/forge-std/src/".to_string() })
+        );
+        assert_eq!(
+            Remapping::parse(" lib/a:@oz/=lib/a/lib/oz/ "),
+            Some(Remapping { context: Some("lib/a".to_string()), prefix: "@oz/".to_string(), target: "lib/a/lib/oz/".to_string() })
+        );
+        assert_eq!(Remapping::parse(":@oz/=lib/oz/").and_then(|remapping| remapping.context), None);
+        assert_eq!(Remapping::parse("=lib/oz/"), None);
+        assert_eq!(Remapping::parse("no-equals-sign"), None);
+    }
+
+    #[test]
+    fn resolve_import_is_relative_to_the_importer_for_dot_paths() {
+        let project = project(&[]);
+        assert_eq!(
+            project.resolve_import(Path::new("/root/test/unit/A.t.sol"), "../utils/Base.sol"),
+            Path::new("/root/test/unit/../utils/Base.sol")
+        );
+        assert_eq!(project.resolve_import(Path::new("/root/test/A.t.sol"), "src/Token.sol"), Path::new("/root/src/Token.sol"));
+    }
+
+    #[test]
+    fn resolve_import_prefers_the_longest_prefix() {
+        let project = project(&["@oz/=lib/oz/", "@oz/contracts/=lib/oz-v5/contracts/"]);
+        let importer = Path::new("/root/test/A.t.sol");
+        assert_eq!(
+            project.resolve_import(importer, "@oz/contracts/token/ERC20.sol"),
+            Path::new("/root/lib/oz-v5/contracts/token/ERC20.sol")
+        );
+        assert_eq!(project.resolve_import(importer, "@oz/utils/Math.sol"), Path::new("/root/lib/oz/utils/Math.sol"));
+    }
+
+    #[test]
+    fn resolve_import_applies_context_remappings_only_inside_their_context() {
+        let project = project(&["@oz/contracts/=lib/oz/contracts/", "lib/vault:@oz/=lib/vault/lib/oz/"]);
+        assert_eq!(
+            project.resolve_import(Path::new("/root/lib/vault/src/Vault.sol"), "@oz/contracts/ERC20.sol"),
+            Path::new("/root/lib/vault/lib/oz/contracts/ERC20.sol")
+        );
+        assert_eq!(
+            project.resolve_import(Path::new("/root/test/A.t.sol"), "@oz/contracts/ERC20.sol"),
+            Path::new("/root/lib/oz/contracts/ERC20.sol")
+        );
+    }
+
+    const CONFIG: &str = r#"
+        [profile.default]
+        test = 'tests' # a literal string
+        out = "build"
+        libs = [
+            "lib",   # first
+            "node_modules",
+        ]
+        remappings = ["@oz/=lib/oz/"]
+        optimizer_runs = 200
+
+        [profile.ci]
+        out = "ci-out"
+        remappings = ["@oz/=lib/oz-ci/"]
+
+        [fmt]
+        line_length = 120
+    "#;
+
+    #[test]
+    fn read_profile_reads_the_default_profile() {
+        let profile = read_profile(CONFIG, "default").unwrap();
+        assert_eq!(profile.test.as_deref(), Some("tests"));
+        assert_eq!(profile.out.as_deref(), Some("build"));
+        assert_eq!(profile.libs, Some(vec!["lib".to_string(), "node_modules".to_string()]));
+        assert_eq!(profile.remappings, Some(vec!["@oz/=lib/oz/".to_string()]));
+    }
+
+    #[test]
+    fn read_profile_overrides_the_default_profile() {
+        let profile = read_profile(CONFIG, "ci").unwrap();
+        assert_eq!(profile.test.as_deref(), Some("tests"));
+        assert_eq!(profile.out.as_deref(), Some("ci-out"));
+        assert_eq!(profile.remappings, Some(vec!["@oz/=lib/oz-ci/".to_string()]));
+        assert_eq!(read_profile(CONFIG, "missing").unwrap(), read_profile(CONFIG, "default").unwrap());
+    }
+
+    #[test]
+    fn read_profile_reads_multi_line_strings() {
+        let profile = read_profile("[profile.default]\nremappings = [\"\"\"a/=lib/a/\"\"\", '''b/=lib/b/''']\n", "default").unwrap();
+        assert_eq!(profile.remappings, Some(vec!["a/=lib/a/".to_string(), "b/=lib/b/".to_string()]));
+        assert_eq!(read_profile("", "default").unwrap(), Profile::default());
+    }
+}
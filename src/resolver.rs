@@ -0,0 +1,342 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::abi_parser::{ContractAbi, ContractFunction};
+use crate::foundry_test_parser::{Expr, TestContract};
+
+/// What a call site resolves to once the ABI overloads are taken into account.
+#[derive(Debug)]
+pub enum Resolution<'a> {
+    /// Exactly one ABI function fits the call.
+    Unique(&'a ContractFunction),
+    /// No function with that name exists.
+    Unknown,
+    /// Functions with that name exist, but none takes this many arguments.
+    ArityMismatch(Vec<usize>),
+    /// Several overloads still fit after looking at the argument types.
+    Ambiguous(Vec<&'a ContractFunction>),
+}
+
+/// Picks the ABI function a test call refers to, using the argument count and
+/// whatever can be inferred about the argument types from the test source.
+pub struct CallResolver<'a> {
+    abi: &'a [ContractFunction],
+    /// Functions of the other contracts the test deploys, by contract name.
+    dependencies: HashMap<&'a str, &'a [ContractFunction]>,
+    variables: HashMap<String, String>,
+    /// Parameters and locals of every function; `None` for names declared
+    /// with different types in different places.
+    locals: HashMap<String, Option<String>>,
+    /// ABI types of the user-defined types in the sources.
+    user_types: HashMap<String, String>,
+    helpers: HashSet<String>,
+}
+
+impl<'a> CallResolver<'a> {
+    pub fn new(test_contract: &TestContract, abi: &'a ContractAbi, dependencies: &'a [ContractAbi]) -> Self {
+        let variables = test_contract.state_variables.iter()
+            .map(|var| (var.name.clone(), var.type_.clone()))
+            .collect();
+        let mut locals: HashMap<String, Option<String>> = HashMap::new();
+        for function in test_contract.functions() {
+            let parameters = function.parameters.iter().map(|param| (&param.name, &param.type_));
+            let declared = function.all_steps().into_iter().flat_map(|step| step.declared());
+            for (name, type_) in parameters.chain(declared) {
+                locals.entry(name.clone())
+                    .and_modify(|known| if known.as_ref() != Some(type_) { *known = None })
+                    .or_insert_with(|| Some(type_.clone()));
+            }
+        }
+        let helpers = test_contract.helpers.iter().map(|helper| helper.name.clone()).collect();
+        let dependencies = dependencies.iter()
+            .filter_map(|dependency| Some((dependency.contract_name.as_deref()?, dependency.functions.as_slice())))
+            .collect();
+        CallResolver { abi: &abi.functions, dependencies, variables, locals, user_types: test_contract.user_types.clone(), helpers }
+    }
+
+    /// True when `receiver` is declared as, or cast to, one of the other
+    /// deployed contracts, whose calls are resolved against that contract's ABI.
+    pub fn is_dependency(&self, receiver: &Expr) -> bool {
+        self.dependency_functions(receiver).is_some()
+    }
+
+    fn dependency_functions(&self, receiver: &Expr) -> Option<&'a [ContractFunction]> {
+        let type_ = match receiver {
+            Expr::Variable { name } => self.declared_type(name)?,
+            receiver => receiver.interface_cast()?.0,
+        };
+        self.dependencies.get(type_).copied()
+    }
+
+    /// The type `name` is declared with, as a state variable or else as a
+    /// parameter or local.
+    fn declared_type(&self, name: &str) -> Option<&str> {
+        self.variables.get(name)
+            .or_else(|| self.locals.get(name)?.as_ref())
+            .map(String::as_str)
+    }
+
+    fn functions_of(&self, receiver: &Expr) -> &'a [ContractFunction] {
+        self.dependency_functions(receiver).unwrap_or(self.abi)
+    }
+
+    pub fn is_state_variable(&self, name: &str) -> bool {
+        self.variables.contains_key(name)
+    }
+
+    /// True when `name` can be a contract or interface type, as opposed to a
+    /// struct or enum declared in the sources.
+    pub fn is_contract_type(&self, name: &str) -> bool {
+        self.user_types.get(name).is_none_or(|type_| type_ == "address")
+    }
+
+    /// True for the test contract's own helper functions, which run locally.
+    pub fn is_helper(&self, name: &str) -> bool {
+        self.helpers.contains(name)
+    }
+
+    /// True for `transfer`, `send` and `call` on a plain address, which send
+    /// ether or raw calldata instead of calling a function from an ABI.
+    pub fn is_address_call(&self, receiver: &Expr, function: &str) -> bool {
+        matches!(function, "transfer" | "send" | "call")
+            && matches!(self.infer_type(receiver).as_deref(), Some("address" | "address payable" | "payable"))
+    }
+
+    /// True when `receiver`'s ABI declares more than one function called `function`.
+    pub fn is_overloaded(&self, receiver: &Expr, function: &str) -> bool {
+        self.functions_of(receiver).iter().filter(|candidate| candidate.name == function).count() > 1
+    }
+
+    /// The function `receiver.function(arguments)` calls, looked up in the ABI
+    /// of the contract `receiver` holds.
+    pub fn resolve(&self, receiver: &Expr, function: &str, arguments: &[Expr]) -> Resolution<'a> {
+        let candidates: Vec<&ContractFunction> = self.functions_of(receiver).iter().filter(|candidate| candidate.name == function).collect();
+        if candidates.is_empty() {
+            return Resolution::Unknown;
+        }
+
+        let by_arity: Vec<&ContractFunction> = candidates.iter()
+            .copied()
+            .filter(|candidate| candidate.inputs.len() == arguments.len())
+            .collect();
+        if by_arity.is_empty() {
+            let mut arities: Vec<usize> = candidates.iter().map(|candidate| candidate.inputs.len()).collect();
+            arities.sort();
+            arities.dedup();
+            return Resolution::ArityMismatch(arities);
+        }
+        if by_arity.len() == 1 {
+            return Resolution::Unique(by_arity[0]);
+        }
+
+        let argument_types: Vec<Option<String>> = arguments.iter().map(|arg| self.infer_type(arg)).collect();
+        let by_type: Vec<&ContractFunction> = by_arity.iter()
+            .copied()
+            .filter(|candidate| {
+                candidate.inputs.iter()
+                    .zip(&argument_types)
+                    .all(|(param, inferred)| inferred.as_deref().is_none_or(|inferred| is_compatible(inferred, &param.type_)))
+            })
+            .collect();
+        match by_type.len() {
+            1 => Resolution::Unique(by_type[0]),
+            0 => Resolution::Ambiguous(by_arity),
+            _ => Resolution::Ambiguous(by_type),
+        }
+    }
+
+    /// How a value of the declared `type_` is passed in the ABI.
+    fn abi_type(&self, type_: &str) -> String {
+        self.user_types.get(type_).cloned().unwrap_or_else(|| type_.to_string())
+    }
+
+    /// Best-effort Solidity type of an expression. Integer literals report the
+    /// pseudo-type `int_const`, which fits any integer parameter.
+    pub fn infer_type(&self, expr: &Expr) -> Option<String> {
+        match expr {
+            Expr::Number { .. } => Some("int_const".to_string()),
+            Expr::Bool { .. } => Some("bool".to_string()),
+            Expr::String { .. } => Some("string_const".to_string()),
+            Expr::Hex { .. } => Some("bytes".to_string()),
+            Expr::Address { .. } => Some("address".to_string()),
+            Expr::Variable { name } => Some(self.abi_type(self.declared_type(name)?)),
+            Expr::Cast { ty, .. } => Some(ty.clone()),
+            Expr::Struct { .. } => Some("tuple".to_string()),
+            Expr::MemberAccess { object, member } => match (object.as_ref(), member.as_str()) {
+                (_, "selector") => Some("bytes4".to_string()),
+                (_, "balance") => Some("uint256".to_string()),
+                (Expr::Variable { name }, "sender" | "origin" | "coinbase") if name == "msg" || name == "tx" || name == "block" => {
+                    Some("address".to_string())
+                }
+                (Expr::Variable { name }, _) if name == "block" => Some("uint256".to_string()),
+                _ => None,
+            },
+            Expr::Call { .. } => {
+                if let Some((type_, _)) = expr.interface_cast() {
+                    return Some(self.abi_type(type_));
+                }
+                let (receiver, function, arguments) = expr.contract_call()?;
+                match self.resolve(receiver, function, arguments) {
+                    Resolution::Unique(resolved) if resolved.outputs.len() == 1 => Some(resolved.outputs[0].type_.clone()),
+                    _ => None,
+                }
+            }
+            Expr::Unary { op, operand } => match op.as_str() {
+                "!" => Some("bool".to_string()),
+                _ => self.infer_type(operand),
+            },
+            Expr::Binary { op, left, right } => match op.as_str() {
+                "<" | ">" | "<=" | ">=" | "==" | "!=" | "&&" | "||" => Some("bool".to_string()),
+                _ => match self.infer_type(left) {
+                    Some(ty) if ty != "int_const" => Some(ty),
+                    _ => self.infer_type(right),
+                },
+            },
+            Expr::Ternary { if_true, if_false, .. } => self.infer_type(if_true).or_else(|| self.infer_type(if_false)),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a value of the `inferred` type can be passed where the ABI expects `expected`.
+fn is_compatible(inferred: &str, expected: &str) -> bool {
+    if inferred == expected {
+        return true;
+    }
+    let is_integer = |ty: &str| ty.starts_with("uint") || ty.starts_with("int");
+    match inferred {
+        "int_const" => is_integer(expected),
+        "string_const" => expected == "string" || expected.starts_with("bytes"),
+        "bytes" => expected.starts_with("bytes") && !expected.contains('['),
+        "address payable" => expected == "address",
+        _ if inferred.starts_with("uint") => expected.starts_with("uint"),
+        _ if inferred.starts_with("int") => expected.starts_with("int") && !expected.starts_with("uint"),
+        // Contract, interface and library typed variables are passed as addresses.
+        _ if inferred.chars().next().is_some_and(char::is_uppercase) => expected == "address",
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi_parser::FunctionParameter;
+    use crate::foundry_test_parser::StateVariable;
+
+    fn function(name: &str, inputs: &[&str]) -> ContractFunction {
+        ContractFunction {
+            name: name.to_string(),
+            inputs: inputs.iter()
+                .map(|type_| FunctionParameter { name: String::new(), type_: type_.to_string(), internal_type: None, components: Vec::new() })
+                .collect(),
+            outputs: Vec::new(),
+            state_mutability: "nonpayable".to_string(),
+        }
+    }
+
+    fn test_contract(variables: &[(&str, &str)]) -> TestContract {
+        TestContract {
+            name: "ResolverTest".to_string(),
+            state_variables: variables.iter()
+                .map(|(name, type_)| StateVariable { name: name.to_string(), type_: type_.to_string(), value: None })
+                .collect(),
+            setup: None,
+            test_functions: Vec::new(),
+            invariants: Vec::new(),
+            after_invariant: None,
+            helpers: Vec::new(),
+            constants: Vec::new(),
+            user_types: HashMap::from([("Token".to_string(), "address".to_string())]),
+        }
+    }
+
+    fn variable(name: &str) -> Expr {
+        Expr::Variable { name: name.to_string() }
+    }
+
+    fn number(value: &str) -> Expr {
+        Expr::Number { value: value.to_string() }
+    }
+
+    fn resolved_inputs(resolution: Resolution) -> Vec<String> {
+        match resolution {
+            Resolution::Unique(function) => function.inputs.iter().map(|input| input.type_.clone()).collect(),
+            other => panic!("expected a unique function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn resolve_picks_safe_transfer_from_by_argument_count() {
+        let abi = ContractAbi {
+            functions: vec![
+                function("safeTransferFrom", &["address", "address", "uint256"]),
+                function("safeTransferFrom", &["address", "address", "uint256", "bytes"]),
+            ],
+            ..Default::default()
+        };
+        let contract = test_contract(&[("nft", "Token"), ("alice", "address"), ("bob", "address")]);
+        let resolver = CallResolver::new(&contract, &abi, &[]);
+
+        let three = [variable("alice"), variable("bob"), number("1")];
+        assert_eq!(resolved_inputs(resolver.resolve(&variable("nft"), "safeTransferFrom", &three)), ["address", "address", "uint256"]);
+        let four = [variable("alice"), variable("bob"), number("1"), Expr::Hex { value: "0x".to_string() }];
+        assert_eq!(resolved_inputs(resolver.resolve(&variable("nft"), "safeTransferFrom", &four)), ["address", "address", "uint256", "bytes"]);
+        assert!(resolver.is_overloaded(&variable("nft"), "safeTransferFrom"));
+    }
+
+    #[test]
+    fn is_compatible_lets_integer_literals_fit_any_integer() {
+        assert!(is_compatible("int_const", "uint256"));
+        assert!(is_compatible("int_const", "uint8"));
+        assert!(is_compatible("int_const", "int128"));
+        assert!(!is_compatible("int_const", "address"));
+        assert!(!is_compatible("int_const", "bytes32"));
+        assert!(is_compatible("uint8", "uint256"));
+        assert!(!is_compatible("uint256", "int256"));
+        assert!(!is_compatible("int256", "uint256"));
+    }
+
+    #[test]
+    fn resolve_passes_contract_typed_variables_as_addresses() {
+        assert!(is_compatible("Token", "address"));
+        assert!(!is_compatible("Token", "uint256"));
+
+        let abi = ContractAbi {
+            functions: vec![function("approve", &["address", "uint256"]), function("approve", &["bytes32", "uint256"])],
+            ..Default::default()
+        };
+        let contract = test_contract(&[("token", "Token"), ("spender", "Token")]);
+        let resolver = CallResolver::new(&contract, &abi, &[]);
+        let arguments = [variable("spender"), number("5")];
+        assert_eq!(resolved_inputs(resolver.resolve(&variable("token"), "approve", &arguments)), ["address", "uint256"]);
+    }
+
+    #[test]
+    fn resolve_reports_overloads_the_arguments_cannot_tell_apart() {
+        let abi = ContractAbi {
+            functions: vec![function("mint", &["uint256"]), function("mint", &["uint128"]), function("mint", &["address"])],
+            ..Default::default()
+        };
+        let contract = test_contract(&[("token", "Token")]);
+        let resolver = CallResolver::new(&contract, &abi, &[]);
+
+        let Resolution::Ambiguous(candidates) = resolver.resolve(&variable("token"), "mint", &[number("1")]) else { panic!() };
+        let candidates: Vec<&str> = candidates.iter().map(|candidate| candidate.inputs[0].type_.as_str()).collect();
+        assert_eq!(candidates, ["uint256", "uint128"]);
+        let Resolution::Ambiguous(candidates) = resolver.resolve(&variable("token"), "mint", &[variable("missing")]) else { panic!() };
+        assert_eq!(candidates.len(), 3);
+    }
+
+    #[test]
+    fn resolve_reports_the_arities_when_none_fits() {
+        let abi = ContractAbi {
+            functions: vec![function("transfer", &["address", "uint256"]), function("transfer", &["address"]), function("transfer", &["address", "uint256"])],
+            ..Default::default()
+        };
+        let contract = test_contract(&[("token", "Token")]);
+        let resolver = CallResolver::new(&contract, &abi, &[]);
+
+        let arguments = [number("1"), number("2"), number("3")];
+        assert!(matches!(resolver.resolve(&variable("token"), "transfer", &arguments), Resolution::ArityMismatch(arities) if arities == [1, 2]));
+        assert!(matches!(resolver.resolve(&variable("token"), "burn", &arguments), Resolution::Unknown));
+    }
+}
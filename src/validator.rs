@@ -0,0 +1,165 @@
+use std::fmt;
+
+use crate::abi_parser::ContractAbi;
+use crate::foundry_test_parser::{is_builtin_receiver, Expr, RevertExpectation, SourceLocation, TestContract, TestFunction, TestStep};
+use crate::resolver::{CallResolver, Resolution};
+
+/// A problem found while checking the test's contract calls against the ABI.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub location: SourceLocation,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// Checks every contract call in `setUp` and the tests, including calls nested
+/// in assertion arguments, against the functions declared in the ABI, and makes
+/// sure every contract the test deploys has bytecode to deploy with and every
+/// custom error and event it expects is declared, and that every `super`
+/// call reaches a base in the sources. `dependencies` are the
+/// artifacts of the other contracts the test deploys.
+pub fn validate(test_contract: &TestContract, abi: &ContractAbi, dependencies: &[ContractAbi]) -> Vec<Diagnostic> {
+    let resolver = CallResolver::new(test_contract, abi, dependencies);
+    let mut diagnostics = Vec::new();
+    for function in test_contract.functions() {
+        validate_function(function, &resolver, &mut diagnostics);
+        validate_deployments(function, abi, dependencies, &mut diagnostics);
+        validate_expected_reverts(function, abi, dependencies, &mut diagnostics);
+        validate_expected_emits(function, abi, dependencies, &mut diagnostics);
+    }
+    diagnostics
+}
+
+fn validate_function(function: &TestFunction, resolver: &CallResolver, diagnostics: &mut Vec<Diagnostic>) {
+    for step in function.all_steps() {
+        let Some(location) = step.loc() else { continue };
+
+        if let TestStep::FunctionCall { contract: Some(contract), function, arguments, .. } = step {
+            if contract == "super" {
+                diagnostics.push(unresolved_super(function, location));
+            } else if !is_builtin_receiver(contract) {
+                let receiver = Expr::Variable { name: contract.clone() };
+                check_call(&receiver, function, arguments, location, resolver, diagnostics);
+            }
+        }
+        if let TestStep::ContractCall { call: Expr::Unsupported { source }, .. } = step {
+            diagnostics.push(Diagnostic {
+                location: location.clone(),
+                message: format!("`{}` is not supported, so the call is left out; only the `value` and `gas` call options are", source),
+            });
+        }
+        for expr in step.expressions() {
+            expr.visit(&mut |expr| {
+                if let Some((receiver, function, arguments)) = expr.contract_call() {
+                    check_call(receiver, function, arguments, location, resolver, diagnostics);
+                }
+                if let Expr::Call { callee, .. } = expr {
+                    if let Expr::MemberAccess { object, member } = callee.as_ref() {
+                        if matches!(object.as_ref(), Expr::Variable { name } if name == "super") {
+                            diagnostics.push(unresolved_super(member, location));
+                        }
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// `super.f(...)` left over once the bases in the sources are merged.
+fn unresolved_super(function: &str, location: &SourceLocation) -> Diagnostic {
+    Diagnostic {
+        location: location.clone(),
+        message: format!("`super.{}` is not declared in any base found in the sources, so the call is left out", function),
+    }
+}
+
+fn check_call(receiver: &Expr, function: &str, arguments: &[Expr], location: &SourceLocation, resolver: &CallResolver, diagnostics: &mut Vec<Diagnostic>) {
+    if resolver.is_address_call(receiver, function) {
+        return;
+    }
+    let message = match resolver.resolve(receiver, function, arguments) {
+        Resolution::Unique(_) => return,
+        Resolution::Unknown => format!("unknown function `{}`: not found in the ABI", function),
+        Resolution::ArityMismatch(arities) => format!(
+            "`{}` is called with {} argument(s), but the ABI expects {}",
+            function,
+            arguments.len(),
+            arities.iter().map(usize::to_string).collect::<Vec<_>>().join(" or ")
+        ),
+        Resolution::Ambiguous(candidates) => format!(
+            "ambiguous call to overloaded `{}`: the arguments fit {}",
+            function,
+            candidates.iter().map(|candidate| candidate.human_readable()).collect::<Vec<_>>().join(", ")
+        ),
+    };
+    diagnostics.push(Diagnostic { location: location.clone(), message });
+}
+
+fn validate_deployments(function: &TestFunction, abi: &ContractAbi, dependencies: &[ContractAbi], diagnostics: &mut Vec<Diagnostic>) {
+    for step in function.all_steps() {
+        let TestStep::Constructor { contract, loc, .. } = step else { continue };
+        if dependencies.iter().any(|dependency| dependency.contract_name.as_deref() == Some(contract) && dependency.bytecode.is_some()) {
+            continue;
+        }
+        let message = match abi.contract_name.as_deref() {
+            _ if abi.bytecode.is_none() => format!(
+                "cannot deploy `{}`: the ABI has no bytecode; pass the Forge artifact (out/<File>.sol/<Contract>.json) to --abi",
+                contract
+            ),
+            Some(name) if name != contract => format!("cannot deploy `{}`: the artifact is for `{}`", contract, name),
+            _ => continue,
+        };
+        diagnostics.push(Diagnostic { location: loc.clone(), message });
+    }
+}
+
+fn validate_expected_reverts(function: &TestFunction, abi: &ContractAbi, dependencies: &[ContractAbi], diagnostics: &mut Vec<Diagnostic>) {
+    for step in function.all_steps() {
+        let TestStep::ExpectRevert { expected: RevertExpectation::CustomError { name, arguments }, loc } = step else { continue };
+        let candidates: Vec<usize> = std::iter::once(abi).chain(dependencies)
+            .flat_map(|abi| &abi.errors)
+            .filter(|error| &error.name == name)
+            .map(|error| error.inputs.len())
+            .collect();
+        let message = match arguments {
+            _ if candidates.is_empty() => format!("expected revert with `{}`, which is not an error in the ABI", name),
+            Some(arguments) if !candidates.contains(&arguments.len()) => format!(
+                "expected revert with `{}` and {} argument(s), but the ABI declares it with {}",
+                name,
+                arguments.len(),
+                candidates.iter().map(usize::to_string).collect::<Vec<_>>().join(" or ")
+            ),
+            _ => continue,
+        };
+        diagnostics.push(Diagnostic { location: loc.clone(), message });
+    }
+}
+
+fn validate_expected_emits(function: &TestFunction, abi: &ContractAbi, dependencies: &[ContractAbi], diagnostics: &mut Vec<Diagnostic>) {
+    for step in function.all_steps() {
+        let TestStep::ExpectEmit { event, arguments, loc, .. } = step else { continue };
+        let candidates: Vec<usize> = std::iter::once(abi).chain(dependencies)
+            .flat_map(|abi| &abi.events)
+            .filter(|candidate| &candidate.name == event)
+            .map(|candidate| candidate.inputs.len())
+            .collect();
+        let message = if candidates.is_empty() {
+            format!("expected event `{}` is not an event in the ABI", event)
+        } else if !candidates.contains(&arguments.len()) {
+            format!(
+                "expected event `{}` is emitted with {} argument(s), but the ABI declares it with {}",
+                event,
+                arguments.len(),
+                candidates.iter().map(usize::to_string).collect::<Vec<_>>().join(" or ")
+            )
+        } else {
+            continue;
+        };
+        diagnostics.push(Diagnostic { location: loc.clone(), message });
+    }
+}